@@ -0,0 +1,178 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::math::{Isometry, Vector};
+use serde::{Deserialize, Serialize};
+
+use crate::rules::Rules;
+use crate::{BoardLabel, BodyId, Game};
+
+// In terms of block size
+pub const WALL_THICKNESS: f32 = 1.0;
+// How far above the floor the walls of `Gapped` start
+const WALL_GAP_HEIGHT: f32 = 2.0;
+// How far the middle of the `Funnel` floor is below the ends
+pub const FUNNEL_DEPTH: f32 = 2.0;
+
+/// The static shape holding the blocks. Rows and lanes are always counted inside the well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoardShape {
+    /// Only a floor, blocks can slide off the sides
+    #[serde(rename = "open")]
+    Open,
+    /// A floor with walls on both sides, like classic Tetris
+    #[serde(rename = "walled")]
+    Walled,
+    /// Walls that stop short of the floor, so blocks can still be pushed out at the bottom
+    #[serde(rename = "gapped")]
+    Gapped,
+    /// Walls and a V-shaped floor sloping down towards the middle
+    #[serde(rename = "funnel")]
+    Funnel,
+}
+
+impl BoardShape {
+    pub fn has_walls(&self) -> bool {
+        *self != Self::Open
+    }
+
+    /// How far the floor dips below the bottom row
+    pub fn floor_depth(&self) -> f32 {
+        match self {
+            Self::Funnel => FUNNEL_DEPTH,
+            _ => 0.0,
+        }
+    }
+}
+
+impl Default for BoardShape {
+    fn default() -> Self {
+        Self::Open
+    }
+}
+
+variant_names!(
+    BoardShape,
+    "board shape",
+    [
+        "open" => Open,
+        "walled" => Walled,
+        "gapped" => Gapped,
+        "funnel" => Funnel,
+    ]
+);
+
+/// One of the side walls of the board. Not the floor, nor the slopes of a funnel.
+pub struct Wall;
+
+/// Spawn the floor and walls of the board
+pub fn spawn_board_shape<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    rules: &Rules,
+    material: Handle<ColorMaterial>,
+) {
+    let floor_y = game.floor_y();
+    let floor_block_height = rules.floor_block_height;
+    let shape = game.board;
+
+    let wall_thickness = if shape.has_walls() {
+        WALL_THICKNESS
+    } else {
+        0.0
+    };
+
+    if shape == BoardShape::Funnel {
+        // Two slopes from the bottom of the walls down to the middle
+        let half_width = game.n_lanes as f32 * 0.5;
+        let length = (half_width * half_width + FUNNEL_DEPTH * FUNNEL_DEPTH).sqrt();
+        let angle = (-FUNNEL_DEPTH).atan2(half_width);
+
+        // Below the sloping line, so the top of the slab is the floor
+        let x = -half_width * 0.5 + angle.sin() * floor_block_height * 0.5;
+        let y = floor_y - FUNNEL_DEPTH * 0.5 - angle.cos() * floor_block_height * 0.5;
+
+        for (x, angle) in [(x, angle), (-x, -angle)].iter() {
+            spawn_static_slab::<B>(
+                commands,
+                game.next_body_id(),
+                rules,
+                material.clone(),
+                Vec2::new(game.origin.x + *x, y),
+                *angle,
+                Vec2::new(length, floor_block_height),
+            );
+        }
+    } else {
+        // The floor reaches under the walls
+        spawn_static_slab::<B>(
+            commands,
+            game.next_body_id(),
+            rules,
+            material.clone(),
+            Vec2::new(game.origin.x, floor_y - floor_block_height * 0.5),
+            0.0,
+            Vec2::new(
+                game.n_lanes as f32 + 2.0 * wall_thickness,
+                floor_block_height,
+            ),
+        );
+    }
+
+    if shape.has_walls() {
+        let top_y = game.top_y();
+        let bottom_y = match shape {
+            BoardShape::Gapped => floor_y + WALL_GAP_HEIGHT,
+            _ => floor_y - shape.floor_depth() - floor_block_height,
+        };
+
+        for x in [
+            game.left_wall_x() - wall_thickness * 0.5,
+            game.right_wall_x() + wall_thickness * 0.5,
+        ]
+        .iter()
+        {
+            let wall = spawn_static_slab::<B>(
+                commands,
+                game.next_body_id(),
+                rules,
+                material.clone(),
+                Vec2::new(*x, (top_y + bottom_y) * 0.5),
+                0.0,
+                Vec2::new(wall_thickness, top_y - bottom_y),
+            );
+            commands.entity(wall).insert(Wall);
+        }
+    }
+}
+
+/// A static box, `size` in terms of block size
+fn spawn_static_slab<B: BoardLabel>(
+    commands: &mut Commands,
+    body_id: BodyId,
+    rules: &Rules,
+    material: Handle<ColorMaterial>,
+    center: Vec2,
+    angle: f32,
+    size: Vec2,
+) -> Entity {
+    commands
+        .spawn()
+        .insert_bundle(SpriteBundle {
+            material,
+            sprite: Sprite::new(size * rules.block_px_size),
+            ..Default::default()
+        })
+        .insert_bundle(RigidBodyBundle {
+            body_type: RigidBodyType::Static,
+            position: Isometry::new(Vector::new(center.x, center.y), angle).into(),
+            ..RigidBodyBundle::default()
+        })
+        .insert_bundle(ColliderBundle {
+            shape: ColliderShape::cuboid(size.x * 0.5, size.y * 0.5),
+            ..ColliderBundle::default()
+        })
+        .insert(RigidBodyPositionSync::Discrete)
+        .insert(body_id)
+        .insert(B::default())
+        .id()
+}
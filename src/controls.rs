@@ -0,0 +1,119 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::replay::Replay;
+use crate::{BoardLabel, OnBoard};
+
+/// Where the controls of a board come from
+#[derive(Clone)]
+pub enum InputSource {
+    /// Every board played from the keyboard uses the same keys
+    Keyboard,
+    /// The controls of a recorded game, which also brings its own seed and rules
+    Replay(Replay),
+    /// The app presses and releases the `OnBoard<B, Controls>` of the board itself, before the
+    /// tick stage runs
+    External,
+}
+
+impl Default for InputSource {
+    fn default() -> Self {
+        Self::Keyboard
+    }
+}
+
+/// The controls available to the player
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Control {
+    Left,
+    Right,
+    RotateCounterClockwise,
+    RotateClockwise,
+    Hold,
+}
+
+impl Control {
+    pub const ALL: [Self; 5] = [
+        Self::Left,
+        Self::Right,
+        Self::RotateCounterClockwise,
+        Self::RotateClockwise,
+        Self::Hold,
+    ];
+
+    fn key_code(&self) -> KeyCode {
+        match self {
+            Self::Left => KeyCode::Left,
+            Self::Right => KeyCode::Right,
+            Self::RotateCounterClockwise => KeyCode::A,
+            Self::RotateClockwise => KeyCode::D,
+            Self::Hold => KeyCode::C,
+        }
+    }
+}
+
+/// The state of the controls during the current tick.
+///
+/// The game reads its input from here rather than from the keyboard,
+/// so that the input can also come from a replay or from the app.
+#[derive(Default)]
+pub struct Controls {
+    pressed: HashSet<Control>,
+    just_pressed: HashSet<Control>,
+    just_released: HashSet<Control>,
+}
+
+impl Controls {
+    pub fn pressed(&self, control: Control) -> bool {
+        self.pressed.contains(&control)
+    }
+
+    pub fn just_pressed(&self, control: Control) -> bool {
+        self.just_pressed.contains(&control)
+    }
+
+    pub fn just_released(&self, control: Control) -> bool {
+        self.just_released.contains(&control)
+    }
+
+    /// Forget what was pressed or released during the previous tick
+    pub fn begin_tick(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+
+    pub fn press(&mut self, control: Control) {
+        if self.pressed.insert(control) {
+            self.just_pressed.insert(control);
+        }
+    }
+
+    pub fn release(&mut self, control: Control) {
+        if self.pressed.remove(&control) {
+            self.just_released.insert(control);
+        }
+    }
+}
+
+/// Every board played from the keyboard is played with the same keys
+pub(crate) fn keyboard_controls<B: BoardLabel>(
+    input: Res<Input<KeyCode>>,
+    mut controls: ResMut<OnBoard<B, Controls>>,
+) {
+    controls.begin_tick();
+
+    for control in Control::ALL.iter() {
+        if input.pressed(control.key_code()) {
+            controls.press(*control);
+        } else {
+            controls.release(*control);
+        }
+    }
+}
+
+/// Controls pressed or released by the app count in one tick only, like keys do
+pub(crate) fn forget_external_presses<B: BoardLabel>(mut controls: ResMut<OnBoard<B, Controls>>) {
+    controls.begin_tick();
+}
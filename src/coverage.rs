@@ -0,0 +1,244 @@
+//! How much of each row is covered by blocks, measured by area so that tilted blocks
+//! straddling two rows count towards both
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::math::Point;
+
+use crate::Game;
+
+/// From 0 to 1, how much of each row is covered by blocks at rest, lowest row first
+#[derive(Default)]
+pub struct RowCoverage(pub Vec<f32>);
+
+/// The area of a block within each row it overlaps
+pub struct BlockCoverage {
+    pub entity: Entity,
+    /// (row, area), the area in terms of block size squared
+    pub rows: Vec<(usize, f32)>,
+}
+
+/// Measure every row of the well. Each block comes with the outlines of its parts in local
+/// coordinates, a single square with sides of 1 unless it's a rigid piece or has been sliced.
+pub fn measure_rows<'a>(
+    game: &Game,
+    blocks: impl Iterator<Item = (Entity, &'a RigidBodyPosition, &'a [Vec<Vec2>])>,
+) -> (RowCoverage, Vec<BlockCoverage>) {
+    let (n_lanes, n_rows) = (game.n_lanes, game.n_rows);
+
+    let mut row_areas = vec![0.0; n_rows];
+    let mut block_coverages = vec![];
+
+    for (entity, position, parts) in blocks {
+        let mut rows: Vec<(usize, f32)> = vec![];
+
+        for outline in parts {
+            for (row, area) in measure_outline(game, position, outline) {
+                row_areas[row] += area;
+                match rows.iter_mut().find(|(block_row, _)| *block_row == row) {
+                    Some((_, block_area)) => *block_area += area,
+                    None => rows.push((row, area)),
+                }
+            }
+        }
+
+        block_coverages.push(BlockCoverage { entity, rows });
+    }
+
+    let coverage = row_areas
+        .into_iter()
+        .map(|area| (area / n_lanes as f32).min(1.0))
+        .collect();
+
+    (RowCoverage(coverage), block_coverages)
+}
+
+/// The area of one convex outline within each row it overlaps
+fn measure_outline(
+    game: &Game,
+    position: &RigidBodyPosition,
+    outline: &[Vec2],
+) -> Vec<(usize, f32)> {
+    let n_rows = game.n_rows;
+    let floor_y = game.floor_y();
+    let (left_wall_x, right_wall_x) = (game.left_wall_x(), game.right_wall_x());
+
+    let corners = world_polygon(position, outline);
+
+    let lowest = corners
+        .iter()
+        .map(|corner| corner.y)
+        .fold(f32::MAX, f32::min);
+    let highest = corners
+        .iter()
+        .map(|corner| corner.y)
+        .fold(f32::MIN, f32::max);
+
+    let first_row = ((lowest - floor_y).floor() as i32).max(0);
+    let last_row = ((highest - floor_y).floor() as i32).min(n_rows as i32 - 1);
+
+    let mut rows = vec![];
+    for row in first_row..=last_row {
+        let bottom = floor_y + row as f32;
+        let area = area_within(&corners, left_wall_x, right_wall_x, bottom, bottom + 1.0);
+
+        if area > 0.0 {
+            rows.push((row as usize, area));
+        }
+    }
+
+    rows
+}
+
+/// A polygon in the local coordinates of a body, moved to where the body is
+pub fn world_polygon(position: &RigidBodyPosition, polygon: &[Vec2]) -> Vec<Vec2> {
+    polygon
+        .iter()
+        .map(|point| {
+            let point = position.position * Point::new(point.x, point.y);
+            Vec2::new(point.x, point.y)
+        })
+        .collect()
+}
+
+/// Area of the convex polygon inside the rectangle, by clipping it against each side
+pub fn area_within(polygon: &[Vec2], left: f32, right: f32, bottom: f32, top: f32) -> f32 {
+    let mut clipped = polygon.to_vec();
+
+    clipped = clip(&clipped, |point| point.x - left);
+    clipped = clip(&clipped, |point| right - point.x);
+    clipped = clip(&clipped, |point| point.y - bottom);
+    clipped = clip(&clipped, |point| top - point.y);
+
+    area(&clipped)
+}
+
+/// Keep the part of the polygon where `distance` is zero or more
+pub fn clip(polygon: &[Vec2], distance: impl Fn(Vec2) -> f32) -> Vec<Vec2> {
+    let mut clipped = vec![];
+
+    for (i, &start) in polygon.iter().enumerate() {
+        let end = polygon[(i + 1) % polygon.len()];
+        let (start_distance, end_distance) = (distance(start), distance(end));
+
+        if start_distance >= 0.0 {
+            clipped.push(start);
+        }
+
+        // The edge crosses the line
+        if (start_distance >= 0.0) != (end_distance >= 0.0) {
+            let t = start_distance / (start_distance - end_distance);
+            clipped.push(start + (end - start) * t);
+        }
+    }
+
+    clipped
+}
+
+pub fn area(polygon: &[Vec2]) -> f32 {
+    let twice_area: f32 = polygon
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let next = polygon[(i + 1) % polygon.len()];
+            point.x * next.y - next.x * point.y
+        })
+        .sum();
+
+    twice_area.abs() * 0.5
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_4;
+
+    use bevy_rapier2d::rapier::math::{Isometry, Vector};
+
+    use super::*;
+    use crate::rules::Rules;
+    use crate::test_util::assert_close;
+    use crate::Block;
+
+    fn body_at(x: f32, y: f32, angle: f32) -> RigidBodyPosition {
+        Isometry::new(Vector::new(x, y), angle).into()
+    }
+
+    fn measure_square(game: &Game, position: &RigidBodyPosition) -> Vec<(usize, f32)> {
+        let parts = vec![Block::square_outline(Vec2::ZERO)];
+        let (_, mut block_coverages) = measure_rows(
+            game,
+            vec![(Entity::new(0), position, parts.as_slice())].into_iter(),
+        );
+
+        block_coverages.pop().unwrap().rows
+    }
+
+    #[test]
+    fn aligned_square_covers_one_row() {
+        let game = Game::new(&Rules::default(), Vec2::ZERO);
+        let position = body_at(game.left_wall_x() + 2.5, game.floor_y() + 3.5, 0.0);
+
+        let rows = measure_square(&game, &position);
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].0, 3);
+        assert_close(rows[0].1, 1.0);
+    }
+
+    #[test]
+    fn tilted_square_is_split_between_rows() {
+        let game = Game::new(&Rules::default(), Vec2::ZERO);
+        // Centered on the line between the first two rows, corners up and down
+        let position = body_at(0.5, game.floor_y() + 1.0, FRAC_PI_4);
+
+        let rows = measure_square(&game, &position);
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 0);
+        assert_eq!(rows[1].0, 1);
+        assert_close(rows[0].1, 0.5);
+        assert_close(rows[1].1, 0.5);
+        assert_close(rows.iter().map(|(_, area)| area).sum(), 1.0);
+    }
+
+    #[test]
+    fn square_is_clipped_at_the_walls() {
+        let game = Game::new(&Rules::default(), Vec2::ZERO);
+        // Half of it beyond each wall
+        let left = body_at(game.left_wall_x(), game.floor_y() + 0.5, 0.0);
+        let right = body_at(game.right_wall_x(), game.floor_y() + 0.5, 0.0);
+
+        for position in [left, right].iter() {
+            let rows = measure_square(&game, position);
+
+            assert_eq!(rows.len(), 1);
+            assert_close(rows[0].1, 0.5);
+        }
+    }
+
+    #[test]
+    fn row_coverage_is_the_covered_fraction() {
+        let rules = Rules::default();
+        let game = Game::new(&rules, Vec2::ZERO);
+        let parts = vec![Block::square_outline(Vec2::ZERO)];
+        let positions: Vec<RigidBodyPosition> = (0..3)
+            .map(|lane| {
+                body_at(
+                    game.left_wall_x() + lane as f32 + 0.5,
+                    game.floor_y() + 0.5,
+                    0.0,
+                )
+            })
+            .collect();
+
+        let (coverage, _) = measure_rows(
+            &game,
+            positions
+                .iter()
+                .map(|position| (Entity::new(0), position, parts.as_slice())),
+        );
+
+        assert_close(coverage.0[0], 3.0 / rules.lanes as f32);
+        assert_close(coverage.0[1], 0.0);
+    }
+}
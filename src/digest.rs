@@ -0,0 +1,48 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{board_name, BoardLabel, BodyId, Tick};
+
+/// Hash of the position and velocity of every rigid body a board spawned.
+///
+/// Two runs of the same replay should produce the same digest at every tick. Entity ids depend on
+/// whatever else the app spawned, so they are left out.
+pub fn physics_digest<'a>(
+    bodies: impl Iterator<Item = (&'a BodyId, &'a RigidBodyPosition, &'a RigidBodyVelocity)>,
+) -> u64 {
+    // Query order is not guaranteed, so sort by spawn order
+    let mut bodies: Vec<_> = bodies.collect();
+    bodies.sort_by_key(|(body_id, _, _)| body_id.0);
+
+    let mut hasher = DefaultHasher::new();
+
+    for (_, position, velocity) in bodies {
+        let isometry = &position.position;
+
+        hasher.write_u32(isometry.translation.x.to_bits());
+        hasher.write_u32(isometry.translation.y.to_bits());
+        hasher.write_u32(isometry.rotation.cos_angle().to_bits());
+        hasher.write_u32(isometry.rotation.sin_angle().to_bits());
+        hasher.write_u32(velocity.linvel.x.to_bits());
+        hasher.write_u32(velocity.linvel.y.to_bits());
+        hasher.write_u32(velocity.angvel.to_bits());
+    }
+
+    hasher.finish()
+}
+
+/// Runs in every tick, after the physics step
+pub fn log_physics_digest<B: BoardLabel>(
+    tick: Res<Tick>,
+    body_query: Query<(&BodyId, &RigidBodyPosition, &RigidBodyVelocity), With<B>>,
+) {
+    info!(
+        "tick {} board {} digest {:016x}",
+        tick.0,
+        board_name::<B>(),
+        physics_digest(body_query.iter())
+    );
+}
@@ -0,0 +1,52 @@
+//! Events for whatever wants to follow the game without being part of it,
+//! like the HUD, audio, telemetry or bots. Each is sent as `OnBoard<B, _>` by the board
+//! labelled `B`.
+
+use crate::TetrominoKind;
+
+/// A new piece appeared at the top of the well, also after a hold
+#[derive(Clone, Debug)]
+pub struct PieceSpawned {
+    pub kind: TetrominoKind,
+}
+
+/// All blocks of the current piece came to rest
+#[derive(Clone, Debug)]
+pub struct PieceSettled;
+
+/// Rows were cleared after a piece settled, or when the stack came to rest again
+#[derive(Clone, Debug)]
+pub struct RowsCleared {
+    /// Counted from the floor, lowest first
+    pub rows: Vec<usize>,
+    /// Number of clears since the last piece settled, including this one
+    pub chain: u32,
+}
+
+/// Rows were cleared by blocks falling into place after an earlier clear.
+/// Also sent as `RowsCleared`.
+#[derive(Clone, Debug)]
+pub struct ChainCleared {
+    pub rows: Vec<usize>,
+    /// 2 for the first clear cascading from the one when the piece settled, and so on
+    pub chain: u32,
+}
+
+/// A block fell out of the board
+#[derive(Clone, Debug)]
+pub struct BlockLost {
+    /// If the block belonged to the piece being controlled, which ends the game
+    pub was_current: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct HealthChanged {
+    /// From 0 to 1, see `Stats::health`
+    pub health: f32,
+}
+
+/// Sent once when a game ends, for whatever reason
+#[derive(Clone, Debug)]
+pub struct GameOver {
+    pub score: u64,
+}
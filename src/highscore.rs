@@ -0,0 +1,277 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::randomizer::{PieceRandomizer, RandomizerKind};
+use crate::rules::Rules;
+use crate::{BoardLabel, Game, OnBoard};
+
+/// How many results are kept
+pub const HIGH_SCORE_COUNT: usize = 10;
+
+/// A finished game, with what is needed to play the same pieces again
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScore {
+    pub score: u64,
+    pub seed: u64,
+    pub randomizer: RandomizerKind,
+    /// Year, month and day in UTC, like 2021-05-17
+    pub date: String,
+    pub rules: Rules,
+}
+
+/// The best results so far, best first, kept in a file under the user data dir
+#[derive(Default)]
+pub struct HighScores {
+    path: Option<PathBuf>,
+    pub entries: Vec<HighScore>,
+    /// Positions in the table of the games that just ended, one for each board that made it
+    pub latest: Vec<usize>,
+}
+
+impl HighScores {
+    /// The table in the user data dir. Without one, the table is empty and can't be saved.
+    pub fn load() -> Self {
+        match dirs::data_dir() {
+            Some(dir) => Self::load_from(dir.join("newtonian-tetris").join("highscores.ron")),
+            None => Self::default(),
+        }
+    }
+
+    /// The table in `path`, where `save` writes it back. A missing or unreadable file gives an
+    /// empty table, so a broken file never stops the game.
+    pub fn load_from(path: PathBuf) -> Self {
+        let entries = if path.exists() {
+            fs::read_to_string(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|text| ron::from_str(&text).map_err(|err| err.to_string()))
+                .unwrap_or_else(|err| {
+                    warn!("Ignoring high scores in {}: {}", path.display(), err);
+                    vec![]
+                })
+        } else {
+            vec![]
+        };
+
+        Self {
+            path: Some(path),
+            entries,
+            latest: vec![],
+        }
+    }
+
+    /// Position in the table, if the score made it
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        // Ties go below the older results
+        let position = self
+            .entries
+            .iter()
+            .position(|existing| existing.score < entry.score)
+            .unwrap_or_else(|| self.entries.len());
+
+        if position >= HIGH_SCORE_COUNT {
+            return None;
+        }
+
+        self.entries.insert(position, entry);
+        self.entries.truncate(HIGH_SCORE_COUNT);
+
+        // The results below the new one move down, maybe out of the table
+        for latest in self.latest.iter_mut() {
+            if *latest >= position {
+                *latest += 1;
+            }
+        }
+        self.latest.retain(|latest| *latest < HIGH_SCORE_COUNT);
+
+        Some(position)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Err("no user data directory".to_string()),
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("could not create {}: {}", dir.display(), err))?;
+        }
+
+        let text = ron::ser::to_string_pretty(&self.entries, ron::ser::PrettyConfig::new())
+            .map_err(|err| format!("could not serialize high scores: {}", err))?;
+
+        fs::write(path, text)
+            .map_err(|err| format!("could not write high scores {}: {}", path.display(), err))
+    }
+}
+
+/// Runs when a game starts, so only the results of the games that end next are marked
+pub fn forget_latest_high_scores(mut high_scores: ResMut<HighScores>) {
+    high_scores.latest.clear();
+}
+
+pub fn record_high_score<B: BoardLabel>(
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    randomizer: Res<OnBoard<B, PieceRandomizer>>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    if game.stats.score == 0 {
+        return;
+    }
+
+    let position = high_scores.insert(HighScore {
+        score: game.stats.score,
+        seed: randomizer.seed(),
+        randomizer: randomizer.strategy_kind(),
+        date: today(),
+        rules: (**rules).clone(),
+    });
+
+    if let Some(position) = position {
+        high_scores.latest.push(position);
+
+        if let Err(err) = high_scores.save() {
+            error!("{}", err);
+        }
+    }
+}
+
+/// The current date in UTC
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+
+    // Days since 1970-01-01 to a civil date, from Howard Hinnant's date algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(score: u64, seed: u64) -> HighScore {
+        HighScore {
+            score,
+            seed,
+            randomizer: RandomizerKind::default(),
+            date: "2021-05-17".to_string(),
+            rules: Rules::default(),
+        }
+    }
+
+    /// A path of its own for each test, as tests run in parallel
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "newtonian-tetris-{}-{}.ron",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn scores(high_scores: &HighScores) -> Vec<u64> {
+        high_scores
+            .entries
+            .iter()
+            .map(|entry| entry.score)
+            .collect()
+    }
+
+    #[test]
+    fn missing_file_gives_empty_table() {
+        let high_scores = HighScores::load_from(temp_path("missing"));
+
+        assert!(high_scores.entries.is_empty());
+    }
+
+    #[test]
+    fn corrupt_file_gives_empty_table() {
+        let path = temp_path("corrupt");
+        fs::write(&path, "[(score: 100, seed:").unwrap();
+
+        let high_scores = HighScores::load_from(path.clone());
+        fs::remove_file(&path).unwrap();
+
+        assert!(high_scores.entries.is_empty());
+    }
+
+    #[test]
+    fn saved_table_loads_again() {
+        let path = temp_path("saved");
+        let mut high_scores = HighScores::load_from(path.clone());
+        high_scores.insert(entry(300, 1));
+        high_scores.insert(entry(100, 2));
+        high_scores.save().unwrap();
+
+        let loaded = HighScores::load_from(path.clone());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(scores(&loaded), vec![300, 100]);
+    }
+
+    #[test]
+    fn ties_go_below_older_results() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry(200, 1));
+        high_scores.insert(entry(100, 2));
+
+        assert_eq!(high_scores.insert(entry(200, 3)), Some(1));
+
+        let seeds: Vec<u64> = high_scores.entries.iter().map(|entry| entry.seed).collect();
+        assert_eq!(seeds, vec![1, 3, 2]);
+    }
+
+    #[test]
+    fn table_keeps_the_best_results() {
+        let mut high_scores = HighScores::default();
+        for score in 1..=HIGH_SCORE_COUNT as u64 {
+            high_scores.insert(entry(score * 100, score));
+        }
+
+        // Too low, and tied with the last result, which is older
+        assert_eq!(high_scores.insert(entry(50, 0)), None);
+        assert_eq!(high_scores.insert(entry(100, 0)), None);
+
+        assert_eq!(
+            high_scores.insert(entry(150, 0)),
+            Some(HIGH_SCORE_COUNT - 1)
+        );
+        assert_eq!(high_scores.entries.len(), HIGH_SCORE_COUNT);
+        assert_eq!(high_scores.entries.last().unwrap().score, 150);
+    }
+
+    #[test]
+    fn latest_results_move_down_and_out() {
+        let mut high_scores = HighScores::default();
+        for score in 1..=HIGH_SCORE_COUNT as u64 {
+            high_scores.insert(entry(score * 100, score));
+        }
+        // The third best and the last
+        high_scores.latest = vec![2, HIGH_SCORE_COUNT - 1];
+
+        assert_eq!(high_scores.insert(entry(5000, 0)), Some(0));
+
+        assert_eq!(high_scores.latest, vec![3]);
+        assert_eq!(
+            high_scores.entries[3].score,
+            (HIGH_SCORE_COUNT as u64 - 2) * 100
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use crate::menu::UiFont;
+use crate::rules::Rules;
+use crate::{BoardLabel, Game, OnBoard, Tick, PREVIEW_MARGIN, TIMESTEP};
+
+// In terms of block size, below the top of the well. Leaves room for the held piece.
+const HUD_TOP_OFFSET: f32 = 4.0;
+const HUD_FONT_SIZE: f32 = 18.0;
+
+/// Text beside the well showing the stats of the current game
+pub struct Hud;
+
+pub fn setup_hud<B: BoardLabel>(
+    mut commands: Commands,
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    font: Res<UiFont>,
+) {
+    let right_x = game.board_left_x() - PREVIEW_MARGIN;
+    let top_y = game.top_y() - HUD_TOP_OFFSET;
+
+    commands
+        .spawn()
+        .insert_bundle(Text2dBundle {
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: HUD_FONT_SIZE,
+                    color: Color::WHITE,
+                },
+                // Grows to the left and down, away from the well
+                TextAlignment {
+                    vertical: VerticalAlign::Top,
+                    horizontal: HorizontalAlign::Right,
+                },
+            ),
+            transform: Transform::from_xyz(
+                right_x * rules.block_px_size,
+                top_y * rules.block_px_size,
+                1.0,
+            ),
+            ..Default::default()
+        })
+        .insert(Hud)
+        .insert(B::default());
+}
+
+pub fn update_hud<B: BoardLabel>(
+    game: Res<OnBoard<B, Game>>,
+    tick: Res<Tick>,
+    mut hud_query: Query<&mut Text, (With<Hud>, With<B>)>,
+) {
+    let stats = &game.stats;
+
+    // Game time rather than wall clock time, so pauses don't count
+    let elapsed_secs = tick.0 as f64 * TIMESTEP;
+    let pieces_per_minute = if elapsed_secs > 0.0 {
+        stats.settled_pieces as f64 * 60.0 / elapsed_secs
+    } else {
+        0.0
+    };
+
+    let value = format!(
+        "Score      {:>5}\n\
+         Time       {:>2}:{:02}\n\
+         Pieces/min {:>5.1}\n\
+         Rows       {:>5}\n\
+         Cleared    {:>5}\n\
+         Generated  {:>5}\n\
+         Lost       {:>5.1}\n\
+         Health     {:>4}%",
+        stats.score,
+        elapsed_secs as u64 / 60,
+        elapsed_secs as u64 % 60,
+        pieces_per_minute,
+        stats.cleared_rows,
+        stats.cleared_blocks,
+        stats.generated_blocks,
+        stats.lost_blocks,
+        (stats.health() * 100.0).round()
+    );
+
+    for mut text in hud_query.iter_mut() {
+        text.sections[0].value = value.clone();
+    }
+}
@@ -0,0 +1,1870 @@
+//! Tetris with real physics: the blocks of a piece are held together by joints, or form one rigid
+//! body, and rows are cleared when they are full of blocks at rest.
+//!
+//! Add [`NewtonianTetrisPlugin`] and Rapier's physics plugin to an app that has the default
+//! plugins, or a headless subset of them, see the binary for an example.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+
+use bevy::ecs::component::Component;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier2d::physics::step_world_system;
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::dynamics::IntegrationParameters;
+use bevy_rapier2d::rapier::math::{Isometry, Point};
+use rand::Rng;
+
+#[macro_use]
+mod names;
+
+pub mod board;
+pub mod controls;
+pub mod coverage;
+mod digest;
+pub mod events;
+mod highscore;
+mod hud;
+mod menu;
+mod meters;
+pub mod pacing;
+pub mod pieces;
+pub mod randomizer;
+pub mod replay;
+pub mod rules;
+pub mod score;
+mod slicing;
+#[cfg(test)]
+mod test_util;
+
+use board::{BoardShape, Wall};
+use controls::{forget_external_presses, keyboard_controls, Control, Controls, InputSource};
+use coverage::RowCoverage;
+use digest::log_physics_digest;
+use events::{
+    BlockLost, ChainCleared, GameOver, HealthChanged, PieceSettled, PieceSpawned, RowsCleared,
+};
+use highscore::{forget_latest_high_scores, record_high_score, HighScores};
+use hud::{setup_hud, update_hud};
+use menu::{
+    despawn_screen_text, exit_on_game_over, pause_game, resume_game, setup_game_over_screen,
+    setup_main_menu, setup_pause_screen, start_on_enter, UiFont,
+};
+use meters::{setup_fill_meters, update_fill_meters, FillMeterMaterials};
+use pacing::one_tick_per_frame;
+use pieces::PieceMode;
+use randomizer::{PieceRandomizer, RandomizerKind};
+use replay::{
+    record_controls, replay_controls, save_replay, save_replay_on_exit, Replay, ReplayPlayer,
+    ReplayRecorder,
+};
+use rules::{reload_rules, RuleOverrides, Rules, RulesWatcher};
+use score::{ClearedRows, ScoreEvent};
+
+/// Everything that is fixed for the lifetime of the plugin
+#[derive(Clone)]
+pub struct GameConfig {
+    pub rules: Rules,
+    /// Seed for the pieces of every game. A random seed is drawn for each game if not set.
+    pub seed: Option<u64>,
+    pub randomizer: RandomizerKind,
+    /// Number of upcoming pieces shown beside the well
+    pub preview: usize,
+    /// Where the controls come from. A replay also brings the seed and rules of the recorded game.
+    pub input: InputSource,
+    /// Record the controls of each game to this replay file
+    pub record: Option<PathBuf>,
+    /// Watch this config file, with these overrides, and apply changes to the rules while running
+    pub watch_rules: Option<(PathBuf, RuleOverrides)>,
+    /// Log a digest of the physics state after each tick
+    pub digest: bool,
+    /// Run without a camera, menus or text, and exit at game over
+    pub headless: bool,
+    /// Middle of the well, in terms of block size. The boards of an app share one physics world,
+    /// so each needs a place of its own, far enough from the others that no block reaches them.
+    pub origin: Vec2,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            rules: Rules::default(),
+            seed: None,
+            randomizer: RandomizerKind::default(),
+            preview: 3,
+            input: InputSource::default(),
+            record: None,
+            watch_rules: None,
+            digest: false,
+            headless: false,
+            origin: Vec2::ZERO,
+        }
+    }
+}
+
+/// Tells the boards of an app apart. Every entity of a board carries its label as a component,
+/// and its resources and events are wrapped in `OnBoard`. Any type with a default will do.
+pub trait BoardLabel: Component + Default {}
+
+impl<T: Component + Default> BoardLabel for T {}
+
+/// The label of the board in an app with only one
+#[derive(Default)]
+pub struct MainBoard;
+
+/// The label of a board without its module path, for the log
+fn board_name<B: BoardLabel>() -> &'static str {
+    let name = std::any::type_name::<B>();
+
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// A resource or event of the board labelled `B`, like `OnBoard<MainBoard, Game>`
+pub struct OnBoard<B, T> {
+    value: T,
+    board: PhantomData<fn() -> B>,
+}
+
+impl<B, T> OnBoard<B, T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            board: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<B, T> Deref for OnBoard<B, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<B, T> DerefMut for OnBoard<B, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<B, T: Default> Default for OnBoard<B, T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<B, T: Clone> Clone for OnBoard<B, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.value.clone())
+    }
+}
+
+impl<B, T: fmt::Debug> fmt::Debug for OnBoard<B, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
+
+/// The game: a board, its pieces, its game logic and, unless headless, its screens.
+///
+/// Needs `RapierPhysicsPlugin::<NoUserData>` in the app. The ticks run in `TickStage`, once per frame
+/// unless the app gives the stage other run criteria, like `pacing::fixed_ticks`.
+///
+/// An app can have several boards, each added with `for_board` and a label of its own, see
+/// `BoardLabel`. They share the game state, the tick count, the physics world and the keyboard:
+/// they start, pause and end together, and need different `GameConfig::origin`s.
+pub struct NewtonianTetrisPlugin<B = MainBoard> {
+    config: GameConfig,
+    board: PhantomData<fn() -> B>,
+}
+
+impl NewtonianTetrisPlugin {
+    pub fn new(config: GameConfig) -> Self {
+        Self::for_board(config)
+    }
+}
+
+impl<B: BoardLabel> NewtonianTetrisPlugin<B> {
+    /// The board labelled `B`, one of several in the app
+    pub fn for_board(config: GameConfig) -> Self {
+        Self {
+            config,
+            board: PhantomData,
+        }
+    }
+}
+
+impl<B: BoardLabel> Plugin for NewtonianTetrisPlugin<B> {
+    fn build(&self, app: &mut AppBuilder) {
+        let config = &self.config;
+
+        let replay = match &config.input {
+            InputSource::Replay(replay) => Some(replay),
+            _ => None,
+        };
+
+        let (seed, randomizer_kind, rules) = match replay {
+            Some(replay) => (replay.seed, replay.randomizer, replay.rules.clone()),
+            None => (
+                config.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+                config.randomizer,
+                config.rules.clone(),
+            ),
+        };
+
+        let replaying = replay.is_some();
+        let recording = config.record.is_some();
+
+        // The first board sets up what all boards share
+        match app.world().get_resource::<PhysicsScale>() {
+            Some(scale) => assert!(
+                scale.0 == rules.block_px_size,
+                "the boards of an app share one physics world, so they need the same block size"
+            ),
+            None => add_shared_systems(app, config, &rules),
+        }
+
+        app.insert_resource(OnBoard::<B, _>::new(Game::new(&rules, config.origin)))
+            .init_resource::<OnBoard<B, Controls>>()
+            .init_resource::<OnBoard<B, RowCoverage>>()
+            .insert_resource(OnBoard::<B, _>::new(PieceRandomizer::new(
+                seed,
+                randomizer_kind,
+                config.preview,
+            )))
+            .insert_resource(OnBoard::<B, _>::new(RestartSeed(
+                replay.map(|replay| replay.seed).or(config.seed),
+            )))
+            .add_event::<OnBoard<B, ScoreEvent>>()
+            .add_event::<OnBoard<B, PieceSpawned>>()
+            .add_event::<OnBoard<B, PieceSettled>>()
+            .add_event::<OnBoard<B, RowsCleared>>()
+            .add_event::<OnBoard<B, ChainCleared>>()
+            .add_event::<OnBoard<B, BlockLost>>()
+            .add_event::<OnBoard<B, HealthChanged>>()
+            .add_event::<OnBoard<B, GameOver>>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(start_game::<B>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(send_game_over::<B>.system()),
+            );
+
+        let mut tick_systems = SystemSet::new()
+            .with_system(
+                tetromino_movement::<B>
+                    .system()
+                    .label(GameSystem::Movement)
+                    .after(GameSystem::Controls)
+                    .before(GameSystem::PhysicsStep),
+            )
+            .with_system(
+                tetromino_hold::<B>
+                    .system()
+                    .label(GameSystem::Movement)
+                    .after(GameSystem::Controls)
+                    .before(GameSystem::PhysicsStep),
+            )
+            .with_system(
+                block_death_detection::<B>
+                    .system()
+                    .label(GameSystem::DeathDetection)
+                    .after(GameSystem::PhysicsStep),
+            )
+            .with_system(
+                tetromino_contact_detection::<B>
+                    .system()
+                    .label(GameSystem::ContactDetection)
+                    .after(GameSystem::PhysicsStep),
+            )
+            .with_system(
+                tetromino_sleep_detection::<B>
+                    .system()
+                    .label(GameSystem::SleepDetection)
+                    .after(GameSystem::DeathDetection)
+                    .after(GameSystem::ContactDetection),
+            )
+            .with_system(
+                retry_blocked_spawn::<B>
+                    .system()
+                    .after(GameSystem::SleepDetection),
+            )
+            .with_system(
+                stack_rest_detection::<B>
+                    .system()
+                    .after(GameSystem::SleepDetection),
+            )
+            .with_system(
+                update_row_coverage::<B>
+                    .system()
+                    .after(GameSystem::SleepDetection),
+            );
+
+        match &config.input {
+            InputSource::Keyboard => {
+                tick_systems = tick_systems.with_system(
+                    keyboard_controls::<B>
+                        .system()
+                        .label(GameSystem::Controls)
+                        .after(GameSystem::Tick),
+                );
+            }
+            InputSource::Replay(replay) => {
+                app.insert_resource(OnBoard::<B, _>::new(ReplayPlayer::from(replay.clone())));
+                tick_systems = tick_systems.with_system(
+                    replay_controls::<B>
+                        .system()
+                        .label(GameSystem::Controls)
+                        .after(GameSystem::Tick),
+                );
+            }
+            // The app sets the controls before the tick, they are only forgotten after it
+            InputSource::External => {
+                tick_systems = tick_systems.with_system(
+                    forget_external_presses::<B>
+                        .system()
+                        .after(GameSystem::SleepDetection),
+                );
+            }
+        }
+
+        if let Some(path) = &config.record {
+            let recorder = ReplayRecorder {
+                path: path.clone(),
+                replay: Replay::new(seed, randomizer_kind, rules.clone()),
+            };
+            recorder.save();
+
+            app.insert_resource(OnBoard::<B, _>::new(recorder))
+                .add_system_set(
+                    SystemSet::on_enter(GameState::GameOver).with_system(save_replay::<B>.system()),
+                )
+                .add_system_to_stage(CoreStage::Last, save_replay_on_exit::<B>.system());
+            tick_systems = tick_systems.with_system(
+                record_controls::<B>
+                    .system()
+                    .after(GameSystem::Controls)
+                    .before(GameSystem::PhysicsStep),
+            );
+        }
+
+        // Changing the rules in the middle of a game would make its replay useless
+        if let Some((path, overrides)) = &config.watch_rules {
+            if replaying || recording {
+                warn!(
+                    "Not watching {} for changes while recording or replaying",
+                    path.display()
+                );
+            } else {
+                app.insert_resource(OnBoard::<B, _>::new(RulesWatcher::new(
+                    path.clone(),
+                    overrides.clone(),
+                )))
+                .add_system(reload_rules::<B>.system());
+            }
+        }
+
+        if config.digest {
+            app.add_system_to_stage(
+                TickStage,
+                log_physics_digest::<B>
+                    .system()
+                    .after(GameSystem::PhysicsStep),
+            );
+        }
+
+        if !config.headless {
+            app.add_system(update_piece_preview::<B>.system())
+                .add_system(update_held_preview::<B>.system())
+                .add_startup_system(setup_hud::<B>.system())
+                .add_system(update_hud::<B>.system())
+                .add_startup_system(setup_fill_meters::<B>.system())
+                .add_system(update_fill_meters::<B>.system())
+                .add_system_set(
+                    SystemSet::on_enter(GameState::Paused)
+                        .with_system(setup_pause_screen::<B>.system()),
+                )
+                .add_system_set(
+                    SystemSet::on_enter(GameState::GameOver).with_system(
+                        setup_game_over_screen::<B>
+                            .system()
+                            .after(GameSystem::HighScore),
+                    ),
+                );
+        }
+
+        // Replayed games are not new results
+        if !config.headless && !replaying {
+            if !app.world().contains_resource::<HighScores>() {
+                app.insert_resource(HighScores::load()).add_system_set(
+                    SystemSet::on_enter(GameState::Playing)
+                        .with_system(forget_latest_high_scores.system()),
+                );
+            }
+
+            app.add_system_set(
+                SystemSet::on_enter(GameState::GameOver)
+                    .with_system(record_high_score::<B>.system().label(GameSystem::HighScore)),
+            );
+        }
+
+        app.insert_resource(OnBoard::<B, _>::new(rules))
+            .add_startup_system(setup_game::<B>.system())
+            .add_system_set_to_stage(TickStage, tick_systems)
+            .add_system(update_health_bar::<B>.system())
+            .add_system(update_lock_bar::<B>.system());
+    }
+}
+
+/// The state, ticks, physics and screens that all boards of an app have in common
+fn add_shared_systems(app: &mut AppBuilder, config: &GameConfig, rules: &Rules) {
+    // Without a player there's no menu to start from
+    let initial_state = if config.headless || matches!(config.input, InputSource::Replay(_)) {
+        GameState::Playing
+    } else {
+        GameState::MainMenu
+    };
+
+    app.insert_resource(PhysicsScale(rules.block_px_size))
+        .init_resource::<Tick>()
+        .add_state(initial_state)
+        .add_stage_after(
+            CoreStage::Update,
+            TickStage,
+            SystemStage::parallel().with_run_criteria(one_tick_per_frame.system()),
+        )
+        .add_startup_system(setup_physics.system())
+        .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_ticks.system()))
+        .add_system_set_to_stage(
+            TickStage,
+            SystemSet::new()
+                .with_system(advance_tick.system().label(GameSystem::Tick))
+                .with_system(begin_physics_step.system().before(GameSystem::PhysicsStep))
+                .with_system(
+                    step_world_system::<NoUserData>
+                        .system()
+                        .label(GameSystem::PhysicsStep)
+                        .after(GameSystem::Tick),
+                )
+                .with_system(end_physics_step.system().after(GameSystem::PhysicsStep)),
+        );
+
+    if config.headless {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::GameOver).with_system(exit_on_game_over.system()),
+        );
+    } else {
+        app.add_startup_system(setup_camera.system())
+            .init_resource::<UiFont>()
+            .init_resource::<FillMeterMaterials>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::MainMenu).with_system(setup_main_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::MainMenu).with_system(start_on_enter.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::MainMenu).with_system(despawn_screen_text.system()),
+            )
+            .add_system(pause_game.system())
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused).with_system(resume_game.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused).with_system(despawn_screen_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver).with_system(start_on_enter.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::GameOver).with_system(despawn_screen_text.system()),
+            );
+    }
+}
+
+/// The stage after the update stage that runs the ticks: the game logic of every board and the
+/// physics step between. Only runs while playing.
+#[derive(StageLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TickStage;
+
+// Simulated seconds per tick. Each tick runs the game logic and one physics step of this length, so
+// that a game plays out the same regardless of how long each frame takes. See `pacing` for how
+// ticks are fit to frames.
+pub const TIMESTEP: f64 = 1.0 / 60.0;
+
+// In terms of block size:
+const HEALTH_BAR_HEIGHT: f32 = 0.5;
+const LOCK_BAR_HEIGHT: f32 = 0.25;
+const PREVIEW_MARGIN: f32 = 1.0;
+const PREVIEW_SLOT_HEIGHT: f32 = 5.0;
+
+// Preview blocks are drawn smaller than the real ones
+const PREVIEW_BLOCK_SCALE: f32 = 0.6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    MainMenu,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+enum GameSystem {
+    Tick,
+    Controls,
+    Movement,
+    PhysicsStep,
+    DeathDetection,
+    ContactDetection,
+    SleepDetection,
+    HighScore,
+}
+
+/// Number of game logic ticks since the game started
+#[derive(Default)]
+struct Tick(u64);
+
+/// Seed given on the command line or by a replay. Used by every new game, otherwise they get a random seed.
+struct RestartSeed(Option<u64>);
+
+/// Pixels per unit of the physics world, the block size of the first board
+struct PhysicsScale(f32);
+
+#[derive(Default)]
+pub struct Stats {
+    pub generated_blocks: i32,
+    pub cleared_blocks: i32,
+    /// In terms of block area, so a fragment counts for as much of a block as is left of it
+    pub lost_blocks: f32,
+    pub cleared_rows: i32,
+    pub settled_pieces: i32,
+    pub score: u64,
+    /// Most clears after a single piece, see `ScoreEvent::chain`
+    pub longest_chain: u32,
+    /// Number of consecutive pieces that have cleared rows
+    pub combo: u32,
+    pub lost_tetromino: bool,
+}
+
+impl Stats {
+    pub fn health(&self) -> f32 {
+        if self.lost_tetromino {
+            0.0
+        } else if self.cleared_blocks == 0 {
+            if self.lost_blocks > 0.0 {
+                0.0
+            } else {
+                1.0
+            }
+        } else {
+            let lost_ratio = self.lost_blocks / self.cleared_blocks as f32;
+
+            1.0 - lost_ratio
+        }
+    }
+}
+
+pub struct Game {
+    n_lanes: usize,
+    n_rows: usize,
+    board: BoardShape,
+    origin: Vec2,
+    pub stats: Stats,
+    tetromino_colors: Vec<Handle<ColorMaterial>>,
+    current_tetromino_kind: Option<TetrominoKind>,
+    current_tetromino_blocks: HashSet<Entity>,
+    current_tetromino_joints: Vec<Entity>,
+    held_tetromino: Option<TetrominoKind>,
+    // Hold may only be used once per piece
+    hold_used: bool,
+    blocked_spawn: Option<BlockedSpawn>,
+    // When the current piece first touched something other than itself
+    lock_start_tick: Option<u64>,
+    // If blocks of the stack have moved since rows were last checked
+    stack_moving: bool,
+    // Number of clears since the last piece settled
+    chain: u32,
+    // Kept across games, only the order matters
+    next_body_id: u64,
+}
+
+impl Game {
+    /// Forget everything about the previous game, keeping the board
+    fn reset(&mut self) {
+        self.stats = Stats::default();
+        self.current_tetromino_kind = None;
+        self.current_tetromino_blocks.clear();
+        self.current_tetromino_joints.clear();
+        self.held_tetromino = None;
+        self.hold_used = false;
+        self.blocked_spawn = None;
+        self.lock_start_tick = None;
+        self.stack_moving = false;
+        self.chain = 0;
+    }
+
+    fn new(rules: &Rules, origin: Vec2) -> Self {
+        Self {
+            n_lanes: rules.lanes,
+            n_rows: rules.rows,
+            board: rules.board,
+            origin,
+            stats: Stats::default(),
+            tetromino_colors: vec![],
+            current_tetromino_kind: None,
+            current_tetromino_blocks: HashSet::new(),
+            current_tetromino_joints: vec![],
+            held_tetromino: None,
+            hold_used: false,
+            blocked_spawn: None,
+            lock_start_tick: None,
+            stack_moving: false,
+            chain: 0,
+            next_body_id: 0,
+        }
+    }
+
+    fn next_body_id(&mut self) -> BodyId {
+        self.next_body_id += 1;
+
+        BodyId(self.next_body_id)
+    }
+
+    fn floor_y(&self) -> f32 {
+        self.origin.y - (self.n_rows as f32) * 0.5
+    }
+
+    /// Top of the well, where pieces spawn
+    fn top_y(&self) -> f32 {
+        self.origin.y + (self.n_rows as f32) * 0.5
+    }
+
+    fn left_wall_x(&self) -> f32 {
+        self.origin.x - (self.n_lanes as f32) * 0.5
+    }
+
+    fn right_wall_x(&self) -> f32 {
+        self.origin.x + (self.n_lanes as f32) * 0.5
+    }
+
+    /// From 1 down to 0, how much of the lock delay of the current piece is left.
+    /// None until the piece touches something.
+    fn lock_remaining(&self, rules: &Rules, tick: &Tick) -> Option<f32> {
+        self.lock_start_tick.map(|start_tick| {
+            let elapsed_secs = (tick.0 - start_tick) as f64 * TIMESTEP;
+
+            (1.0 - elapsed_secs / rules.lock_delay as f64).max(0.0) as f32
+        })
+    }
+
+    /// Outer side of the left wall, or the left end of the floor without walls
+    fn board_left_x(&self) -> f32 {
+        self.left_wall_x() - self.wall_thickness()
+    }
+
+    /// Outer side of the right wall, or the right end of the floor without walls
+    fn board_right_x(&self) -> f32 {
+        self.right_wall_x() + self.wall_thickness()
+    }
+
+    fn wall_thickness(&self) -> f32 {
+        if self.board.has_walls() {
+            board::WALL_THICKNESS
+        } else {
+            0.0
+        }
+    }
+
+    /// If a block at this position has left the board for good
+    fn in_kill_zone(&self, rules: &Rules, x: f32, y: f32) -> bool {
+        y < self.floor_y() - self.board.floor_depth() - rules.floor_block_height - rules.kill_depth
+            || x < self.board_left_x() - rules.kill_side_distance
+            || x > self.board_right_x() + rules.kill_side_distance
+    }
+}
+
+fn setup_physics(
+    scale: Res<PhysicsScale>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+    mut integration_parameters: ResMut<IntegrationParameters>,
+) {
+    // While we want our sprite to look ~40 px square, we want to keep the physics units smaller
+    // to prevent float rounding problems. To do this, we set the scale factor in RapierConfiguration
+    // and divide our sprite_size by the scale.
+    rapier_config.scale = scale.0;
+
+    // One step per tick, always of the same length. Rapier must not add steps of its own to catch
+    // up with slow frames, the ticks already do.
+    rapier_config.time_dependent_number_of_timesteps = false;
+    integration_parameters.dt = TIMESTEP as f32;
+
+    // Rapier steps the world in the update stage too, every frame. Only the step in the tick stage
+    // may run, see `begin_physics_step`.
+    rapier_config.physics_pipeline_active = false;
+}
+
+fn setup_game<B: BoardLabel>(
+    mut commands: Commands,
+    mut game: ResMut<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    game.tetromino_colors = vec![
+        materials.add(Color::rgb_u8(0, 244, 243).into()),
+        materials.add(Color::rgb_u8(238, 243, 0).into()),
+        materials.add(Color::rgb_u8(177, 0, 254).into()),
+        materials.add(Color::rgb_u8(27, 0, 250).into()),
+        materials.add(Color::rgb_u8(252, 157, 0).into()),
+        materials.add(Color::rgb_u8(0, 247, 0).into()),
+        materials.add(Color::rgb_u8(255, 0, 0).into()),
+    ];
+
+    setup_board::<B>(&mut commands, &mut game, &rules, materials);
+}
+
+/// Runs when entering the playing state, for all boards at once
+fn start_ticks(mut tick: ResMut<Tick>) {
+    *tick = Tick::default();
+}
+
+/// Runs when entering the playing state. Clears away the previous game, if there was one.
+#[allow(clippy::too_many_arguments)]
+fn start_game<B: BoardLabel>(
+    mut commands: Commands,
+    mut game: ResMut<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    restart_seed: Res<OnBoard<B, RestartSeed>>,
+    mut randomizer: ResMut<OnBoard<B, PieceRandomizer>>,
+    mut controls: ResMut<OnBoard<B, Controls>>,
+    recorder: Option<ResMut<OnBoard<B, ReplayRecorder>>>,
+    player: Option<ResMut<OnBoard<B, ReplayPlayer>>>,
+    mut spawned_events: EventWriter<OnBoard<B, PieceSpawned>>,
+    block_query: Query<Entity, (With<Block>, With<B>)>,
+    mut played_before: Local<bool>,
+) {
+    if *played_before {
+        for block_entity in block_query.iter() {
+            commands.entity(block_entity).despawn_recursive();
+        }
+        for joint in &game.current_tetromino_joints {
+            commands.entity(*joint).despawn();
+        }
+        game.reset();
+
+        let seed = restart_seed.0.unwrap_or_else(|| rand::thread_rng().gen());
+        randomizer.reset(seed);
+        **controls = Controls::default();
+
+        // The replay file holds the latest game
+        if let Some(mut recorder) = recorder {
+            recorder.replay = Replay::new(seed, randomizer.strategy_kind(), (**rules).clone());
+            recorder.save();
+        }
+
+        if let Some(mut player) = player {
+            player.rewind();
+        }
+    }
+    *played_before = true;
+
+    info!(
+        "Piece sequence seed: {}, randomizer: {}",
+        randomizer.seed(),
+        randomizer.strategy_kind()
+    );
+
+    let kind = randomizer.next_kind();
+    spawn_tetromino(&mut commands, &mut game, &rules, &mut spawned_events, kind);
+}
+
+/// Switch Rapier on just for the step of this tick
+fn begin_physics_step(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = true;
+}
+
+fn end_physics_step(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = false;
+}
+
+fn setup_camera(mut commands: Commands) {
+    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TetrominoKind {
+    I,
+    O,
+    T,
+    J,
+    L,
+    S,
+    Z,
+}
+
+impl TetrominoKind {
+    const ALL: [Self; 7] = [
+        Self::I,
+        Self::O,
+        Self::T,
+        Self::J,
+        Self::L,
+        Self::S,
+        Self::Z,
+    ];
+
+    fn layout(&self) -> TetrominoLayout {
+        match self {
+            Self::I => TetrominoLayout {
+                coords: [(1, 1), (1, 0), (1, -1), (1, -2)],
+                joints: vec![(0, 1), (1, 2), (2, 3)],
+            },
+            Self::O => TetrominoLayout {
+                coords: [(0, 0), (1, 0), (1, -1), (0, -1)],
+                joints: vec![(0, 1), (1, 2), (2, 3), (1, 0)],
+            },
+            Self::T => TetrominoLayout {
+                coords: [(0, 0), (1, 0), (2, 0), (1, -1)],
+                joints: vec![(0, 1), (1, 2), (1, 3)],
+            },
+            Self::J => TetrominoLayout {
+                coords: [(1, 0), (1, -1), (1, -2), (0, -2)],
+                joints: vec![(0, 1), (1, 2), (2, 3)],
+            },
+            Self::L => TetrominoLayout {
+                coords: [(1, 0), (1, -1), (1, -2), (2, -2)],
+                joints: vec![(0, 1), (1, 2), (2, 3)],
+            },
+            Self::S => TetrominoLayout {
+                coords: [(0, -1), (1, -1), (1, 0), (2, 0)],
+                joints: vec![(0, 1), (1, 2), (2, 3)],
+            },
+            Self::Z => TetrominoLayout {
+                coords: [(0, 0), (1, 0), (1, -1), (2, -1)],
+                joints: vec![(0, 1), (1, 2), (2, 3)],
+            },
+        }
+    }
+}
+
+struct TetrominoLayout {
+    coords: [(i32, i32); 4],
+    joints: Vec<(usize, usize)>,
+}
+
+/// A block of a piece, a whole rigid piece, or a fragment of either left after a row was
+/// cleared through it
+struct Block {
+    kind: TetrominoKind,
+    // Convex outlines of the parts of the body in its local coordinates, counter-clockwise,
+    // in terms of block size. One for a block, one per cell for a rigid piece.
+    parts: Vec<Vec<Vec2>>,
+}
+
+impl Block {
+    fn square(kind: TetrominoKind) -> Self {
+        Self {
+            kind,
+            parts: vec![Self::square_outline(Vec2::ZERO)],
+        }
+    }
+
+    fn square_outline(center: Vec2) -> Vec<Vec2> {
+        vec![
+            center + Vec2::new(-0.5, -0.5),
+            center + Vec2::new(0.5, -0.5),
+            center + Vec2::new(0.5, 0.5),
+            center + Vec2::new(-0.5, 0.5),
+        ]
+    }
+}
+
+struct LockBar;
+
+struct HealthBar {
+    value: f32,
+}
+
+/// A non-physical block showing an upcoming piece
+struct PreviewBlock;
+
+/// A non-physical block showing the held piece
+struct HeldBlock;
+
+fn setup_board<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    rules: &Rules,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let floor_y = game.floor_y();
+    let block_px_size = rules.block_px_size;
+    let floor_block_height = rules.floor_block_height;
+
+    // Add floor, and walls if the board has them
+    board::spawn_board_shape::<B>(
+        commands,
+        game,
+        rules,
+        materials.add(Color::rgb(0.5, 0.5, 0.5).into()),
+    );
+
+    // Add health bar
+    commands
+        .spawn()
+        .insert_bundle(SpriteBundle {
+            material: materials.add(Color::rgb(1.0, 1.0, 1.0).into()),
+            sprite: Sprite::new(Vec2::new(
+                (game.n_lanes as f32 - 2.0) * block_px_size,
+                block_px_size * HEALTH_BAR_HEIGHT,
+            )),
+            transform: Transform {
+                translation: Vec3::new(
+                    (game.left_wall_x() + 1.0) * block_px_size,
+                    (floor_y - (floor_block_height / 2.0)) * block_px_size,
+                    2.0,
+                ),
+                rotation: Quat::IDENTITY,
+                scale: Vec3::new(0.0, 1.0, 1.0),
+            },
+            ..Default::default()
+        })
+        .insert(HealthBar { value: 0.0 })
+        .insert(B::default());
+
+    // Add lock bar, above the well
+    commands
+        .spawn()
+        .insert_bundle(SpriteBundle {
+            material: materials.add(Color::rgb(1.0, 0.6, 0.0).into()),
+            sprite: Sprite::new(Vec2::new(
+                game.n_lanes as f32 * block_px_size,
+                block_px_size * LOCK_BAR_HEIGHT,
+            )),
+            transform: Transform {
+                translation: Vec3::new(
+                    game.origin.x * block_px_size,
+                    (game.top_y() + LOCK_BAR_HEIGHT) * block_px_size,
+                    2.0,
+                ),
+                rotation: Quat::IDENTITY,
+                scale: Vec3::new(0.0, 1.0, 1.0),
+            },
+            ..Default::default()
+        })
+        .insert(LockBar)
+        .insert(B::default());
+}
+
+/// When the game spawned a body, counting from the first one. Entity ids depend on everything else
+/// in the app, like the camera and the text, this only on the game.
+pub struct BodyId(u64);
+
+/// A joint between the blocks of a piece
+pub struct PieceJoint;
+
+/// Blocks of the board labelled `B`, with what it takes to tell whether they are at rest and where
+type BlockQuery<'a, B> = Query<
+    'a,
+    (
+        Entity,
+        &'a RigidBodyActivation,
+        &'a RigidBodyPosition,
+        &'a Block,
+        &'a BodyId,
+    ),
+    With<B>,
+>;
+
+/// The next piece, waiting for blocks in the way to move
+struct BlockedSpawn {
+    kind: TetrominoKind,
+    // The game is over if the way is still blocked at this tick
+    give_up_tick: u64,
+}
+
+/// Where the blocks of a new piece go, as (lane, row)
+fn spawn_cells(game: &Game, kind: TetrominoKind) -> Vec<(i32, i32)> {
+    let lane = (game.n_lanes as i32 / 2) - 1;
+    let row = game.n_rows as i32 - 1;
+
+    kind.layout()
+        .coords
+        .iter()
+        .map(|(x, y)| (lane + x, row + y))
+        .collect()
+}
+
+/// If any collider other than `ignored`, or any of `new_outlines`, overlaps the cells where a new
+/// piece would go. `new_outlines` are the convex outlines, in world coordinates, of bodies spawned
+/// in this tick, which the physics world doesn't know about yet.
+fn spawn_area_blocked(
+    game: &Game,
+    kind: TetrominoKind,
+    ignored: &[Entity],
+    new_outlines: &[Vec<Vec2>],
+    query_pipeline: &QueryPipeline,
+    collider_query: &QueryPipelineColliderComponentsQuery,
+) -> bool {
+    let collider_set = QueryPipelineColliderComponentsSet(collider_query);
+
+    // A bit smaller than a block, so that blocks merely touching the area don't count
+    let half_size = 0.45;
+    let shape = ColliderShape::cuboid(half_size, half_size);
+
+    spawn_cells(game, kind).into_iter().any(|(lane, row)| {
+        let x = game.left_wall_x() + lane as f32 + 0.5;
+        let y = game.floor_y() + row as f32 + 0.5;
+
+        let new_body_in_the_way = new_outlines.iter().any(|outline| {
+            coverage::area_within(
+                outline,
+                x - half_size,
+                x + half_size,
+                y - half_size,
+                y + half_size,
+            ) > 0.0
+        });
+        if new_body_in_the_way {
+            return true;
+        }
+
+        let mut blocked = false;
+        query_pipeline.intersections_with_shape(
+            &collider_set,
+            &Isometry::translation(x, y),
+            &*shape,
+            InteractionGroups::all(),
+            None,
+            |handle| {
+                blocked = !ignored.contains(&handle.entity());
+                // Keep looking until something is in the way
+                !blocked
+            },
+        );
+
+        blocked
+    })
+}
+
+fn spawn_tetromino<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    rules: &Rules,
+    spawned_events: &mut EventWriter<OnBoard<B, PieceSpawned>>,
+    kind: TetrominoKind,
+) {
+    let TetrominoLayout { coords, joints } = kind.layout();
+    let cells = spawn_cells(game, kind);
+
+    if rules.pieces == PieceMode::Rigid {
+        let piece_entity = pieces::spawn_rigid_piece::<B>(commands, game, rules, kind, &cells);
+        start_tetromino(game, spawned_events, kind, vec![piece_entity], vec![]);
+        return;
+    }
+
+    let block_entities: Vec<Entity> = cells
+        .into_iter()
+        .map(|(lane, row)| spawn_block::<B>(commands, game, rules, kind, lane, row))
+        .collect();
+
+    let joint_entities: Vec<Entity> = joints
+        .iter()
+        .map(|(i, j)| {
+            let x_dir = coords[*j].0 as f32 - coords[*i].0 as f32;
+            let y_dir = coords[*j].1 as f32 - coords[*i].1 as f32;
+
+            let anchor_1 = Vec2::new(x_dir * 0.5, y_dir * 0.5).into();
+            let anchor_2 = Vec2::new(x_dir * -0.5, y_dir * -0.5).into();
+
+            commands
+                .spawn()
+                .insert_bundle((
+                    JointBuilderComponent::new(
+                        BallJoint::new(anchor_1, anchor_2),
+                        block_entities[*i],
+                        block_entities[*j],
+                    ),
+                    PieceJoint,
+                    B::default(),
+                ))
+                .id()
+        })
+        .collect();
+
+    start_tetromino(game, spawned_events, kind, block_entities, joint_entities);
+}
+
+fn start_tetromino<B: BoardLabel>(
+    game: &mut Game,
+    spawned_events: &mut EventWriter<OnBoard<B, PieceSpawned>>,
+    kind: TetrominoKind,
+    block_entities: Vec<Entity>,
+    joint_entities: Vec<Entity>,
+) {
+    // Counted per cell, whether or not the cells are separate bodies
+    game.stats.generated_blocks += kind.layout().coords.len() as i32;
+    game.lock_start_tick = None;
+
+    game.current_tetromino_kind = Some(kind);
+    game.current_tetromino_blocks = block_entities.into_iter().collect();
+    game.current_tetromino_joints = joint_entities;
+
+    spawned_events.send(OnBoard::new(PieceSpawned { kind }));
+}
+
+fn spawn_block<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    rules: &Rules,
+    kind: TetrominoKind,
+    lane: i32,
+    row: i32,
+) -> Entity {
+    // x, y is the center of the block
+    let x = game.left_wall_x() + lane as f32 + 0.5;
+    let y = game.floor_y() + row as f32 + 0.5;
+
+    commands
+        .spawn()
+        .insert_bundle(SpriteBundle {
+            material: game.tetromino_colors[kind as usize].clone(),
+            sprite: Sprite::new(Vec2::new(rules.block_px_size, rules.block_px_size)),
+            ..Default::default()
+        })
+        .insert_bundle(RigidBodyBundle {
+            position: [x, y].into(),
+            damping: RigidBodyDamping {
+                linear_damping: rules.linear_damping,
+                angular_damping: 0.0,
+            },
+            ..RigidBodyBundle::default()
+        })
+        .insert_bundle(ColliderBundle {
+            shape: ColliderShape::cuboid(0.5, 0.5),
+            // For the lock delay
+            flags: ColliderFlags {
+                active_events: ActiveEvents::CONTACT_EVENTS,
+                ..ColliderFlags::default()
+            },
+            ..ColliderBundle::default()
+        })
+        .insert(RigidBodyPositionSync::Discrete)
+        .insert(Block::square(kind))
+        .insert(game.next_body_id())
+        .insert(B::default())
+        .id()
+}
+
+/// What is left of a block after slicing off a cleared row
+fn spawn_fragment<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    rules: &Rules,
+    meshes: &mut Assets<Mesh>,
+    kind: TetrominoKind,
+    position: &RigidBodyPosition,
+    parts: Vec<Vec<Vec2>>,
+) {
+    let shape = match slicing::parts_collider(&parts) {
+        Some(shape) => shape,
+        None => return,
+    };
+
+    commands
+        .spawn()
+        .insert_bundle(SpriteBundle {
+            material: game.tetromino_colors[kind as usize].clone(),
+            mesh: meshes.add(slicing::parts_mesh(&parts)),
+            // The mesh is in terms of block size
+            sprite: Sprite::new(Vec2::new(rules.block_px_size, rules.block_px_size)),
+            ..Default::default()
+        })
+        .insert_bundle(RigidBodyBundle {
+            position: position.position.into(),
+            damping: RigidBodyDamping {
+                linear_damping: rules.linear_damping,
+                angular_damping: 0.0,
+            },
+            ..RigidBodyBundle::default()
+        })
+        .insert_bundle(ColliderBundle {
+            shape,
+            flags: ColliderFlags {
+                active_events: ActiveEvents::CONTACT_EVENTS,
+                ..ColliderFlags::default()
+            },
+            ..ColliderBundle::default()
+        })
+        .insert(RigidBodyPositionSync::Discrete)
+        .insert(Block { kind, parts })
+        .insert(game.next_body_id())
+        .insert(B::default());
+}
+
+fn advance_tick(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
+
+fn tetromino_movement<B: BoardLabel>(
+    controls: Res<OnBoard<B, Controls>>,
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    mut forces_query: Query<(&mut RigidBodyForces, &Block), With<B>>,
+) {
+    let movement = controls.pressed(Control::Right) as i8 - controls.pressed(Control::Left) as i8;
+    let torque = controls.pressed(Control::RotateCounterClockwise) as i8
+        - controls.pressed(Control::RotateClockwise) as i8;
+
+    for block_entity in &game.current_tetromino_blocks {
+        if let Ok((mut forces, block)) = forces_query.get_mut(*block_entity) {
+            // A rigid piece is pushed as hard as the separate blocks of a jointed one
+            let n_parts = block.parts.len() as f32;
+            if movement != 0 {
+                forces.force =
+                    Vec2::new(movement as f32 * rules.movement_force * n_parts, 0.0).into();
+            }
+            if torque != 0 {
+                forces.torque = torque as f32 * rules.torque * n_parts;
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn tetromino_hold<B: BoardLabel>(
+    mut commands: Commands,
+    controls: Res<OnBoard<B, Controls>>,
+    mut game: ResMut<OnBoard<B, Game>>,
+    mut state: ResMut<State<GameState>>,
+    rules: Res<OnBoard<B, Rules>>,
+    tick: Res<Tick>,
+    mut randomizer: ResMut<OnBoard<B, PieceRandomizer>>,
+    mut spawned_events: EventWriter<OnBoard<B, PieceSpawned>>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+) {
+    if !controls.just_pressed(Control::Hold) || game.hold_used || game.stats.health() <= 0.0 {
+        return;
+    }
+
+    let current_kind = match game.current_tetromino_kind {
+        Some(kind) => kind,
+        None => return,
+    };
+
+    for joint in game.current_tetromino_joints.drain(..) {
+        commands.entity(joint).despawn();
+    }
+
+    // The held blocks are not lost, they were never really part of the game
+    let held_blocks: Vec<Entity> = game.current_tetromino_blocks.drain().collect();
+    game.stats.generated_blocks -= current_kind.layout().coords.len() as i32;
+    for block_entity in held_blocks.iter() {
+        commands.entity(*block_entity).despawn_recursive();
+    }
+
+    let kind = match game.held_tetromino.replace(current_kind) {
+        Some(held_kind) => held_kind,
+        None => randomizer.next_kind(),
+    };
+
+    // The held blocks are still in the physics world until the end of the tick
+    let blocked = spawn_area_blocked(
+        &game,
+        kind,
+        &held_blocks,
+        &[],
+        &query_pipeline,
+        &collider_query,
+    );
+    spawn_or_wait(
+        &mut commands,
+        &mut game,
+        &mut state,
+        &rules,
+        &tick,
+        &mut spawned_events,
+        kind,
+        blocked,
+    );
+    game.hold_used = true;
+}
+
+/// Start the lock delay when the current piece first touches the stack or floor
+fn tetromino_contact_detection<B: BoardLabel>(
+    mut game: ResMut<OnBoard<B, Game>>,
+    tick: Res<Tick>,
+    mut contact_events: EventReader<ContactEvent>,
+    wall_query: Query<(), With<Wall>>,
+) {
+    for contact_event in contact_events.iter() {
+        if let ContactEvent::Started(handle_1, handle_2) = contact_event {
+            // Sliding down a wall doesn't count as landing
+            if wall_query.get(handle_1.entity()).is_ok()
+                || wall_query.get(handle_2.entity()).is_ok()
+            {
+                continue;
+            }
+
+            let current_1 = game.current_tetromino_blocks.contains(&handle_1.entity());
+            let current_2 = game.current_tetromino_blocks.contains(&handle_2.entity());
+
+            // The blocks of a piece touch each other all the time
+            if current_1 != current_2 && game.lock_start_tick.is_none() {
+                game.lock_start_tick = Some(tick.0);
+            }
+        }
+    }
+}
+
+/// What settling a piece and clearing rows tell the rest of the app
+#[derive(SystemParam)]
+struct PieceEvents<'a, B: BoardLabel> {
+    score: EventWriter<'a, OnBoard<B, ScoreEvent>>,
+    spawned: EventWriter<'a, OnBoard<B, PieceSpawned>>,
+    settled: EventWriter<'a, OnBoard<B, PieceSettled>>,
+    cleared: EventWriter<'a, OnBoard<B, RowsCleared>>,
+    chain: EventWriter<'a, OnBoard<B, ChainCleared>>,
+    health: EventWriter<'a, OnBoard<B, HealthChanged>>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn tetromino_sleep_detection<B: BoardLabel>(
+    mut commands: Commands,
+    mut game: ResMut<OnBoard<B, Game>>,
+    mut state: ResMut<State<GameState>>,
+    rules: Res<OnBoard<B, Rules>>,
+    mut randomizer: ResMut<OnBoard<B, PieceRandomizer>>,
+    mut events: PieceEvents<B>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    tick: Res<Tick>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+    block_query: BlockQuery<B>,
+) {
+    // No piece while waiting for a blocked spawn
+    if game.current_tetromino_blocks.is_empty() {
+        return;
+    }
+
+    let all_blocks_sleeping = game.current_tetromino_blocks.iter().all(|block_entity| {
+        block_query
+            .get(*block_entity)
+            .ok()
+            .map(|(_, activation, _, _, _)| (activation.sleeping))
+            .unwrap_or(false)
+    });
+
+    // A piece that keeps wobbling would otherwise stall the game forever
+    let lock_expired = game
+        .lock_remaining(&rules, &tick)
+        .map_or(false, |remaining| remaining <= 0.0);
+
+    if all_blocks_sleeping || lock_expired {
+        for joint in game.current_tetromino_joints.drain(..) {
+            commands.entity(joint).despawn();
+        }
+        // The blocks are part of the stack now
+        game.current_tetromino_blocks.clear();
+        game.current_tetromino_kind = None;
+        game.lock_start_tick = None;
+
+        game.stats.settled_pieces += 1;
+        events.settled.send(OnBoard::new(PieceSettled));
+
+        let health_before = game.stats.health();
+
+        // Checked here, stack_rest_detection only looks at the stack again once it moves
+        game.stack_moving = false;
+        game.chain = 0;
+
+        let cleared = clear_filled_rows(
+            &mut commands,
+            &mut game,
+            &rules,
+            &mut meshes,
+            &mut events.cleared,
+            &block_query,
+        );
+        if let Some(score_event) = score::award(&mut game.stats, &cleared) {
+            events.score.send(OnBoard::new(score_event));
+        }
+
+        let health = game.stats.health();
+        if health != health_before {
+            events.health.send(OnBoard::new(HealthChanged { health }));
+        }
+
+        if health > 0.0 {
+            let kind = randomizer.next_kind();
+            game.hold_used = false;
+
+            // The cleared blocks are still in the physics world until the end of the tick, what
+            // is left of them not yet
+            let blocked = spawn_area_blocked(
+                &game,
+                kind,
+                &cleared.blocks,
+                &cleared.fragments,
+                &query_pipeline,
+                &collider_query,
+            );
+            spawn_or_wait(
+                &mut commands,
+                &mut game,
+                &mut state,
+                &rules,
+                &tick,
+                &mut events.spawned,
+                kind,
+                blocked,
+            );
+        } else {
+            // Overwrite, block_death_detection may have ended the game in this tick already
+            state.overwrite_set(GameState::GameOver).unwrap();
+        }
+    }
+}
+
+/// Spawn the next piece, unless something is in its way. Then wait for the way to clear, or end
+/// the game if the rules don't allow waiting.
+#[allow(clippy::too_many_arguments)]
+fn spawn_or_wait<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    state: &mut State<GameState>,
+    rules: &Rules,
+    tick: &Tick,
+    spawned_events: &mut EventWriter<OnBoard<B, PieceSpawned>>,
+    kind: TetrominoKind,
+    blocked: bool,
+) {
+    if !blocked {
+        spawn_tetromino(commands, game, rules, spawned_events, kind);
+    } else if rules.spawn_wait > 0.0 {
+        warn!("The way is blocked for the next piece, waiting for it to clear");
+        game.blocked_spawn = Some(BlockedSpawn {
+            kind,
+            give_up_tick: tick.0 + (rules.spawn_wait as f64 / TIMESTEP).ceil() as u64,
+        });
+    } else {
+        info!("Topped out");
+        state.overwrite_set(GameState::GameOver).unwrap();
+    }
+}
+
+/// Spawn the piece that was blocked once the way is clear, or end the game when it takes too long
+#[allow(clippy::too_many_arguments)]
+fn retry_blocked_spawn<B: BoardLabel>(
+    mut commands: Commands,
+    mut game: ResMut<OnBoard<B, Game>>,
+    mut state: ResMut<State<GameState>>,
+    rules: Res<OnBoard<B, Rules>>,
+    tick: Res<Tick>,
+    mut spawned_events: EventWriter<OnBoard<B, PieceSpawned>>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+) {
+    let (kind, give_up_tick) = match &game.blocked_spawn {
+        Some(blocked_spawn) => (blocked_spawn.kind, blocked_spawn.give_up_tick),
+        None => return,
+    };
+
+    if !spawn_area_blocked(&game, kind, &[], &[], &query_pipeline, &collider_query) {
+        game.blocked_spawn = None;
+        spawn_tetromino(&mut commands, &mut game, &rules, &mut spawned_events, kind);
+    } else if tick.0 >= give_up_tick {
+        game.blocked_spawn = None;
+        info!("Topped out");
+        state.overwrite_set(GameState::GameOver).unwrap();
+    }
+}
+
+/// Check for full rows again whenever the stack comes to rest, like when blocks above a cleared row
+/// have dropped down. Clears after the one when the piece settled are chains.
+fn stack_rest_detection<B: BoardLabel>(
+    mut commands: Commands,
+    mut game: ResMut<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut events: PieceEvents<B>,
+    block_query: BlockQuery<B>,
+) {
+    let stack_awake = block_query
+        .iter()
+        .any(|(block_entity, activation, _, _, _)| {
+            !activation.sleeping && !game.current_tetromino_blocks.contains(&block_entity)
+        });
+
+    if stack_awake {
+        game.stack_moving = true;
+        return;
+    }
+
+    if !game.stack_moving {
+        return;
+    }
+    game.stack_moving = false;
+
+    let health_before = game.stats.health();
+
+    let cleared = clear_filled_rows(
+        &mut commands,
+        &mut game,
+        &rules,
+        &mut meshes,
+        &mut events.cleared,
+        &block_query,
+    );
+    if cleared.rows.is_empty() {
+        // The stack settled without a clear, so the chain is over
+        game.chain = 0;
+        return;
+    }
+
+    if let Some(score_event) = score::award(&mut game.stats, &cleared) {
+        events.score.send(OnBoard::new(score_event));
+    }
+
+    if cleared.chain > 1 {
+        events.chain.send(OnBoard::new(ChainCleared {
+            rows: cleared.rows,
+            chain: cleared.chain,
+        }));
+    }
+
+    let health = game.stats.health();
+    if health != health_before {
+        events.health.send(OnBoard::new(HealthChanged { health }));
+    }
+}
+
+fn clear_filled_rows<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    rules: &Rules,
+    meshes: &mut Assets<Mesh>,
+    cleared_events: &mut EventWriter<OnBoard<B, RowsCleared>>,
+    block_query: &BlockQuery<B>,
+) -> ClearedRows {
+    // Only sleeping blocks count.. So disregard blocks "falling off"
+    // that are in the row
+    let (coverage, block_coverages) =
+        coverage::measure_rows(game, resting_stack(game, block_query).into_iter());
+
+    let mut cleared = ClearedRows {
+        rows: coverage
+            .0
+            .iter()
+            .enumerate()
+            .filter(|(_, coverage)| **coverage >= rules.row_clear_coverage)
+            .map(|(row, _)| row)
+            .collect(),
+        chain: 0,
+        blocks: vec![],
+        fragments: vec![],
+        alignment: 0.0,
+    };
+
+    if cleared.rows.is_empty() {
+        return cleared;
+    }
+
+    let floor_y = game.floor_y();
+    let left_wall_x = game.left_wall_x();
+
+    for block_coverage in block_coverages {
+        let area_in_cleared_rows: f32 = block_coverage
+            .rows
+            .iter()
+            .filter(|(row, _)| cleared.rows.contains(row))
+            .map(|(_, area)| area)
+            .sum();
+
+        // A corner grazing a cleared row would only leave a sliver, so the block stays whole
+        if area_in_cleared_rows < slicing::MIN_FRAGMENT_AREA {
+            continue;
+        }
+
+        let block_entity = block_coverage.entity;
+        let (_, _, position, block, _) = block_query.get(block_entity).unwrap();
+
+        // Each square of a rigid piece, or what is left of it, sits in a cell of its own
+        let part_alignment_sum: f32 = block
+            .parts
+            .iter()
+            .map(|part| {
+                let middle =
+                    part.iter().fold(Vec2::ZERO, |sum, point| sum + *point) / part.len() as f32;
+                let middle = position.position * Point::new(middle.x, middle.y);
+                let wall_distance = middle.x - left_wall_x;
+                let floor_distance = middle.y - floor_y;
+
+                score::block_alignment(
+                    position,
+                    (
+                        wall_distance - wall_distance.floor(),
+                        floor_distance - floor_distance.floor(),
+                    ),
+                )
+            })
+            .sum();
+        cleared.alignment += part_alignment_sum / block.parts.len() as f32;
+
+        // The cleared rows cut through the block, the parts outside them stay. Parts of a rigid
+        // piece between the same two cleared rows stay one body.
+        for fragment in slicing::fragments_outside_rows(game, position, &block.parts, &cleared.rows)
+        {
+            cleared.fragments.extend(
+                fragment
+                    .iter()
+                    .map(|part| coverage::world_polygon(position, part)),
+            );
+            spawn_fragment::<B>(
+                commands, game, rules, meshes, block.kind, position, fragment,
+            );
+        }
+
+        cleared.blocks.push(block_entity);
+        commands.entity(block_entity).despawn_recursive();
+    }
+
+    // Counted as whole blocks, however they were sliced
+    game.stats.cleared_blocks += (cleared.rows.len() * game.n_lanes) as i32;
+    game.stats.cleared_rows += cleared.rows.len() as i32;
+
+    if !cleared.blocks.is_empty() {
+        cleared.alignment /= cleared.blocks.len() as f32;
+    }
+
+    game.chain += 1;
+    cleared.chain = game.chain;
+
+    cleared_events.send(OnBoard::new(RowsCleared {
+        rows: cleared.rows.clone(),
+        chain: cleared.chain,
+    }));
+
+    cleared
+}
+
+/// The blocks of the stack at rest, in the order they were spawned. Unlike the order of a query,
+/// that doesn't depend on what else is in the app, so sums over the stack always come out the same.
+fn resting_stack<'a, B: BoardLabel>(
+    game: &Game,
+    block_query: &'a BlockQuery<'_, B>,
+) -> Vec<(Entity, &'a RigidBodyPosition, &'a [Vec<Vec2>])> {
+    let mut blocks: Vec<_> = block_query
+        .iter()
+        .filter(|(block_entity, activation, _, _, _)| {
+            activation.sleeping && !game.current_tetromino_blocks.contains(block_entity)
+        })
+        .collect();
+    blocks.sort_by_key(|(_, _, _, _, body_id)| body_id.0);
+
+    blocks
+        .into_iter()
+        .map(|(block_entity, _, position, block, _)| {
+            (block_entity, position, block.parts.as_slice())
+        })
+        .collect()
+}
+
+/// Measure the rows for whoever wants to know how close they are to clearing
+fn update_row_coverage<B: BoardLabel>(
+    game: Res<OnBoard<B, Game>>,
+    mut row_coverage: ResMut<OnBoard<B, RowCoverage>>,
+    block_query: BlockQuery<B>,
+) {
+    let (coverage, _) =
+        coverage::measure_rows(&game, resting_stack(&game, &block_query).into_iter());
+
+    **row_coverage = coverage;
+}
+
+fn block_death_detection<B: BoardLabel>(
+    mut commands: Commands,
+    mut game: ResMut<OnBoard<B, Game>>,
+    mut state: ResMut<State<GameState>>,
+    rules: Res<OnBoard<B, Rules>>,
+    mut lost_events: EventWriter<OnBoard<B, BlockLost>>,
+    mut health_events: EventWriter<OnBoard<B, HealthChanged>>,
+    block_query: Query<(Entity, &RigidBodyPosition, &Block), With<B>>,
+) {
+    let health_before = game.stats.health();
+
+    for (block_entity, position, block) in block_query.iter() {
+        let translation = position.position.translation;
+
+        if game.in_kill_zone(&rules, translation.x, translation.y) {
+            let was_current = game.current_tetromino_blocks.contains(&block_entity);
+            if was_current {
+                game.stats.lost_tetromino = true;
+                state.overwrite_set(GameState::GameOver).unwrap();
+            }
+
+            game.stats.lost_blocks += block
+                .parts
+                .iter()
+                .map(|part| coverage::area(part))
+                .sum::<f32>();
+            commands.entity(block_entity).despawn_recursive();
+
+            lost_events.send(OnBoard::new(BlockLost { was_current }));
+        }
+    }
+
+    let health = game.stats.health();
+    if health != health_before {
+        health_events.send(OnBoard::new(HealthChanged { health }));
+    }
+}
+
+/// Both the lost piece and the lost health can end the game in the same tick, so the event is
+/// sent on entering the state rather than where the state is set
+fn send_game_over<B: BoardLabel>(
+    game: Res<OnBoard<B, Game>>,
+    mut game_over_events: EventWriter<OnBoard<B, GameOver>>,
+) {
+    game_over_events.send(OnBoard::new(GameOver {
+        score: game.stats.score,
+    }));
+}
+
+fn update_health_bar<B: BoardLabel>(
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    mut health_bar_query: Query<(&mut HealthBar, &mut Transform), With<B>>,
+) {
+    let health = game.stats.health();
+
+    let half_width = (game.n_lanes - 2) as f32 * 0.5;
+
+    for (mut healthbar, mut transform) in health_bar_query.iter_mut() {
+        let delta = health - healthbar.value;
+        healthbar.value += delta * 0.1;
+
+        transform.translation.x =
+            ((game.left_wall_x() + 1.0) + half_width * healthbar.value) * rules.block_px_size;
+        transform.scale.x = healthbar.value;
+    }
+}
+
+fn update_lock_bar<B: BoardLabel>(
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    tick: Res<Tick>,
+    mut lock_bar_query: Query<&mut Transform, (With<LockBar>, With<B>)>,
+) {
+    let remaining = game.lock_remaining(&rules, &tick).unwrap_or(0.0);
+
+    for mut transform in lock_bar_query.iter_mut() {
+        transform.scale.x = remaining;
+    }
+}
+
+fn update_piece_preview<B: BoardLabel>(
+    mut commands: Commands,
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    randomizer: Res<OnBoard<B, PieceRandomizer>>,
+    preview_query: Query<Entity, (With<PreviewBlock>, With<B>)>,
+) {
+    if !randomizer.is_changed() {
+        return;
+    }
+
+    for preview_entity in preview_query.iter() {
+        commands.entity(preview_entity).despawn();
+    }
+
+    let left_x = game.board_right_x() + PREVIEW_MARGIN;
+    let top_y = game.top_y();
+
+    for (slot, kind) in randomizer.upcoming().enumerate() {
+        let slot_top_y = top_y - slot as f32 * PREVIEW_SLOT_HEIGHT * PREVIEW_BLOCK_SCALE;
+
+        for block_entity in
+            spawn_preview_tetromino::<B>(&mut commands, &game, &rules, kind, left_x, slot_top_y)
+        {
+            commands.entity(block_entity).insert(PreviewBlock);
+        }
+    }
+}
+
+fn update_held_preview<B: BoardLabel>(
+    mut commands: Commands,
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    mut shown_kind: Local<Option<TetrominoKind>>,
+    held_query: Query<Entity, (With<HeldBlock>, With<B>)>,
+) {
+    if *shown_kind == game.held_tetromino {
+        return;
+    }
+
+    for held_entity in held_query.iter() {
+        commands.entity(held_entity).despawn();
+    }
+
+    if let Some(kind) = game.held_tetromino {
+        let left_x = game.board_left_x() - PREVIEW_MARGIN - 3.0 * PREVIEW_BLOCK_SCALE;
+        let top_y = game.top_y();
+
+        for block_entity in
+            spawn_preview_tetromino::<B>(&mut commands, &game, &rules, kind, left_x, top_y)
+        {
+            commands.entity(block_entity).insert(HeldBlock);
+        }
+    }
+
+    *shown_kind = game.held_tetromino;
+}
+
+/// Spawn sprites for a piece at preview scale, below `top_y` and to the right of `left_x`
+fn spawn_preview_tetromino<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &Game,
+    rules: &Rules,
+    kind: TetrominoKind,
+    left_x: f32,
+    top_y: f32,
+) -> Vec<Entity> {
+    // Layout coords have the topmost row at y = 1
+    kind.layout()
+        .coords
+        .iter()
+        .map(|(x, y)| {
+            let block_x = left_x + (*x as f32 + 0.5) * PREVIEW_BLOCK_SCALE;
+            let block_y = top_y + (*y as f32 - 1.5) * PREVIEW_BLOCK_SCALE;
+
+            commands
+                .spawn()
+                .insert_bundle(SpriteBundle {
+                    material: game.tetromino_colors[kind as usize].clone(),
+                    sprite: Sprite::new(Vec2::new(
+                        rules.block_px_size * PREVIEW_BLOCK_SCALE,
+                        rules.block_px_size * PREVIEW_BLOCK_SCALE,
+                    )),
+                    transform: Transform::from_xyz(
+                        block_x * rules.block_px_size,
+                        block_y * rules.block_px_size,
+                        0.0,
+                    ),
+                    ..Default::default()
+                })
+                .insert(B::default())
+                .id()
+        })
+        .collect()
+}
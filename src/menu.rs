@@ -0,0 +1,186 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use bevy::window::WindowFocused;
+
+use crate::highscore::HighScores;
+use crate::rules::Rules;
+use crate::{BoardLabel, Game, GameState, OnBoard};
+
+/// The bundled font, so text never depends on the fonts installed on the system
+pub struct UiFont(pub Handle<Font>);
+
+impl FromWorld for UiFont {
+    fn from_world(world: &mut World) -> Self {
+        let font =
+            Font::try_from_bytes(include_bytes!("../assets/fonts/DejaVuSansMono.ttf").to_vec())
+                .expect("the bundled font is valid");
+
+        let mut fonts = world.get_resource_mut::<Assets<Font>>().unwrap();
+
+        Self(fonts.add(font))
+    }
+}
+
+/// Text shown in front of the boards in the menu and game over states
+pub struct ScreenText;
+
+pub fn setup_main_menu(commands: Commands, font: Res<UiFont>) {
+    spawn_screen_text(
+        commands,
+        &font,
+        Vec2::ZERO,
+        "NEWTONIAN TETRIS\n\nPress Enter to start",
+    );
+}
+
+fn pause_pressed(input: &Input<KeyCode>) -> bool {
+    input.just_pressed(KeyCode::P) || input.just_pressed(KeyCode::Escape)
+}
+
+/// The state change runs the systems of the new state in the same frame, which would see the
+/// key as just pressed again and toggle straight back
+fn consume_pause_keys(input: &mut Input<KeyCode>) {
+    input.reset(KeyCode::P);
+    input.reset(KeyCode::Escape);
+}
+
+/// Pause a game on the pause keys, or when the window loses focus
+pub fn pause_game(
+    mut input: ResMut<Input<KeyCode>>,
+    mut focus_events: EventReader<WindowFocused>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let focus_lost = focus_events.iter().any(|event| !event.focused);
+
+    if state.current() != &GameState::Playing {
+        return;
+    }
+
+    if pause_pressed(&input) || focus_lost {
+        consume_pause_keys(&mut input);
+        // Fails if the game ended and the state hasn't changed yet
+        state.push(GameState::Paused).ok();
+    }
+}
+
+pub fn resume_game(mut input: ResMut<Input<KeyCode>>, mut state: ResMut<State<GameState>>) {
+    if pause_pressed(&input) {
+        consume_pause_keys(&mut input);
+        state.pop().ok();
+    }
+}
+
+pub fn setup_pause_screen<B: BoardLabel>(
+    commands: Commands,
+    font: Res<UiFont>,
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+) {
+    let stats = &game.stats;
+
+    spawn_screen_text(
+        commands,
+        &font,
+        game.origin * rules.block_px_size,
+        &format!(
+            "PAUSED\n\n\
+             Score            {:>5}\n\
+             Generated blocks {:>5}\n\
+             Cleared blocks   {:>5}\n\
+             Lost blocks      {:>5.1}\n\
+             Health           {:>4}%\n\n\
+             Press P to resume",
+            stats.score,
+            stats.generated_blocks,
+            stats.cleared_blocks,
+            stats.lost_blocks,
+            (stats.health() * 100.0).round()
+        ),
+    );
+}
+
+pub fn setup_game_over_screen<B: BoardLabel>(
+    commands: Commands,
+    font: Res<UiFont>,
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    high_scores: Option<Res<HighScores>>,
+) {
+    let mut text = format!("GAME OVER\n\nScore {}\n\n", game.stats.score);
+
+    if let Some(high_scores) = high_scores {
+        if !high_scores.entries.is_empty() {
+            text.push_str("HIGH SCORES\n");
+        }
+
+        for (position, entry) in high_scores.entries.iter().enumerate() {
+            let marker = if high_scores.latest.contains(&position) {
+                '>'
+            } else {
+                ' '
+            };
+
+            text.push_str(&format!(
+                "{}{:>2}. {:>7}  {}  {:>2}x{:<2}  {}\n",
+                marker,
+                position + 1,
+                entry.score,
+                entry.date,
+                entry.rules.lanes,
+                entry.rules.rows,
+                entry.randomizer
+            ));
+        }
+
+        text.push('\n');
+    }
+
+    text.push_str("Press Enter to play again");
+
+    spawn_screen_text(commands, &font, game.origin * rules.block_px_size, &text);
+}
+
+/// Start a game from the main menu, or a new one after game over
+pub fn start_on_enter(mut input: ResMut<Input<KeyCode>>, mut state: ResMut<State<GameState>>) {
+    if input.just_pressed(KeyCode::Return) {
+        // So nothing else sees it in the frame the game starts
+        input.reset(KeyCode::Return);
+        state.set(GameState::Playing).ok();
+    }
+}
+
+pub fn despawn_screen_text(mut commands: Commands, text_query: Query<Entity, With<ScreenText>>) {
+    for text_entity in text_query.iter() {
+        commands.entity(text_entity).despawn();
+    }
+}
+
+/// Without a player there's nobody to start another game
+pub fn exit_on_game_over(mut app_exit: EventWriter<AppExit>) {
+    info!("Game over");
+    app_exit.send(AppExit);
+}
+
+/// Text centered on `center`, in pixels
+fn spawn_screen_text(mut commands: Commands, font: &UiFont, center: Vec2, text: &str) {
+    commands
+        .spawn()
+        .insert_bundle(Text2dBundle {
+            text: Text::with_section(
+                text,
+                TextStyle {
+                    font: font.0.clone(),
+                    font_size: 32.0,
+                    color: Color::WHITE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Center,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            // In front of the blocks
+            transform: Transform::from_translation(center.extend(10.0)),
+            ..Default::default()
+        })
+        .insert(ScreenText);
+}
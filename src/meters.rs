@@ -0,0 +1,93 @@
+use bevy::prelude::*;
+
+use crate::coverage::RowCoverage;
+use crate::rules::Rules;
+use crate::{BoardLabel, Game, OnBoard};
+
+// In terms of block size. Between the board and the piece preview.
+const METER_GAP: f32 = 0.15;
+const METER_WIDTH: f32 = 0.5;
+const METER_HEIGHT: f32 = 0.8;
+
+/// A bar beside the well showing how much of a row is covered
+pub struct FillMeter {
+    row: usize,
+}
+
+pub struct FillMeterMaterials {
+    filling: Handle<ColorMaterial>,
+    // At or above the clear threshold
+    full: Handle<ColorMaterial>,
+}
+
+impl FromWorld for FillMeterMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+
+        Self {
+            filling: materials.add(Color::rgb(0.35, 0.35, 0.35).into()),
+            full: materials.add(Color::rgb(1.0, 1.0, 1.0).into()),
+        }
+    }
+}
+
+pub fn setup_fill_meters<B: BoardLabel>(
+    mut commands: Commands,
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    materials: Res<FillMeterMaterials>,
+) {
+    for row in 0..game.n_rows {
+        commands
+            .spawn()
+            .insert_bundle(SpriteBundle {
+                material: materials.filling.clone(),
+                sprite: Sprite::new(Vec2::new(
+                    METER_WIDTH * rules.block_px_size,
+                    METER_HEIGHT * rules.block_px_size,
+                )),
+                transform: Transform {
+                    translation: Vec3::new(
+                        meter_x(&game, 0.0) * rules.block_px_size,
+                        (game.floor_y() + row as f32 + 0.5) * rules.block_px_size,
+                        2.0,
+                    ),
+                    rotation: Quat::IDENTITY,
+                    scale: Vec3::new(0.0, 1.0, 1.0),
+                },
+                ..Default::default()
+            })
+            .insert(FillMeter { row })
+            .insert(B::default());
+    }
+}
+
+pub fn update_fill_meters<B: BoardLabel>(
+    game: Res<OnBoard<B, Game>>,
+    rules: Res<OnBoard<B, Rules>>,
+    row_coverage: Res<OnBoard<B, RowCoverage>>,
+    materials: Res<FillMeterMaterials>,
+    mut meter_query: Query<(&FillMeter, &mut Transform, &mut Handle<ColorMaterial>), With<B>>,
+) {
+    if !row_coverage.is_changed() && !rules.is_changed() {
+        return;
+    }
+
+    for (meter, mut transform, mut material) in meter_query.iter_mut() {
+        let coverage = row_coverage.0.get(meter.row).copied().unwrap_or(0.0);
+
+        transform.translation.x = meter_x(&game, coverage) * rules.block_px_size;
+        transform.scale.x = coverage;
+
+        *material = if coverage >= rules.row_clear_coverage {
+            materials.full.clone()
+        } else {
+            materials.filling.clone()
+        };
+    }
+}
+
+/// Center of a meter filled this much, growing away from the well
+fn meter_x(game: &Game, coverage: f32) -> f32 {
+    game.board_right_x() + METER_GAP + METER_WIDTH * coverage * 0.5
+}
@@ -0,0 +1,39 @@
+//! Names for the variants of the enums that are chosen in config files and on the command line
+
+/// Gives a fieldless enum a table of names, `NAMES`, and implements `Display` and `FromStr` with
+/// it. `$what` describes the enum in the error for an unknown name.
+macro_rules! variant_names {
+    ($type:ident, $what:literal, [$($name:literal => $variant:ident),+ $(,)?]) => {
+        impl $type {
+            const NAMES: &'static [(&'static str, Self)] = &[$(($name, Self::$variant)),+];
+        }
+
+        impl std::fmt::Display for $type {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                let (name, _) = Self::NAMES.iter().find(|(_, value)| value == self).unwrap();
+
+                write!(f, "{}", name)
+            }
+        }
+
+        impl std::str::FromStr for $type {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Self::NAMES
+                    .iter()
+                    .find(|(name, _)| *name == s)
+                    .map(|(_, value)| *value)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = Self::NAMES.iter().map(|(name, _)| *name).collect();
+                        format!(
+                            "unknown {} `{}`, expected one of: {}",
+                            $what,
+                            s,
+                            names.join(", ")
+                        )
+                    })
+            }
+        }
+    };
+}
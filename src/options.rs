@@ -0,0 +1,117 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use newtonian_tetris::randomizer::RandomizerKind;
+use newtonian_tetris::rules::RuleOverrides;
+
+/// Command line options
+pub struct Options {
+    /// Run without a window or renderer
+    pub headless: bool,
+    /// Seed for the piece sequence. Random if not given.
+    pub seed: Option<u64>,
+    /// Strategy for picking pieces
+    pub randomizer: RandomizerKind,
+    /// Number of upcoming pieces to show
+    pub preview: usize,
+    /// Record the game to this replay file
+    pub record: Option<PathBuf>,
+    /// Play back this replay file instead of reading the keyboard
+    pub replay: Option<PathBuf>,
+    /// Log a digest of the physics state every tick
+    pub digest: bool,
+    /// Load rules from this config file
+    pub config: Option<PathBuf>,
+    pub rule_overrides: RuleOverrides,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            headless: false,
+            seed: None,
+            randomizer: RandomizerKind::default(),
+            preview: 3,
+            record: None,
+            replay: None,
+            digest: false,
+            config: None,
+            rule_overrides: RuleOverrides::default(),
+        }
+    }
+}
+
+impl Options {
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => options.headless = true,
+                "--seed" => options.seed = Some(parse_value(&arg, args.next())?),
+                "--randomizer" => options.randomizer = parse_value(&arg, args.next())?,
+                "--preview" => options.preview = parse_value(&arg, args.next())?,
+                "--record" => options.record = Some(parse_value(&arg, args.next())?),
+                "--digest" => options.digest = true,
+                "--config" => options.config = Some(parse_value(&arg, args.next())?),
+                "--lanes" => options.rule_overrides.lanes = Some(parse_value(&arg, args.next())?),
+                "--rows" => options.rule_overrides.rows = Some(parse_value(&arg, args.next())?),
+                "--board" => options.rule_overrides.board = Some(parse_value(&arg, args.next())?),
+                "--pieces" => options.rule_overrides.pieces = Some(parse_value(&arg, args.next())?),
+                "--movement-force" => {
+                    options.rule_overrides.movement_force = Some(parse_value(&arg, args.next())?)
+                }
+                "--torque" => options.rule_overrides.torque = Some(parse_value(&arg, args.next())?),
+                "--block-size" => {
+                    options.rule_overrides.block_px_size = Some(parse_value(&arg, args.next())?)
+                }
+                "--floor-height" => {
+                    options.rule_overrides.floor_block_height =
+                        Some(parse_value(&arg, args.next())?)
+                }
+                "--linear-damping" => {
+                    options.rule_overrides.linear_damping = Some(parse_value(&arg, args.next())?)
+                }
+                "--kill-depth" => {
+                    options.rule_overrides.kill_depth = Some(parse_value(&arg, args.next())?)
+                }
+                "--kill-side-distance" => {
+                    options.rule_overrides.kill_side_distance =
+                        Some(parse_value(&arg, args.next())?)
+                }
+                "--spawn-wait" => {
+                    options.rule_overrides.spawn_wait = Some(parse_value(&arg, args.next())?)
+                }
+                "--lock-delay" => {
+                    options.rule_overrides.lock_delay = Some(parse_value(&arg, args.next())?)
+                }
+                "--row-clear-coverage" => {
+                    options.rule_overrides.row_clear_coverage =
+                        Some(parse_value(&arg, args.next())?)
+                }
+                "replay" => options.replay = Some(parse_value(&arg, args.next())?),
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_value<T>(flag: &str, value: Option<String>) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = value.ok_or_else(|| format!("`{}` requires a value", flag))?;
+
+    value
+        .parse()
+        .map_err(|err| format!("invalid value `{}` for `{}`: {}", value, flag, err))
+}
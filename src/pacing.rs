@@ -0,0 +1,83 @@
+use bevy::ecs::schedule::ShouldRun;
+use bevy::prelude::*;
+
+use crate::{BodyId, GameState, PieceJoint, TIMESTEP};
+
+// More ticks than this in one frame and the game slows down instead of falling further behind
+const MAX_TICKS_PER_FRAME: u32 = 4;
+
+#[derive(Default)]
+pub struct TickPacing {
+    // Real time not yet simulated
+    accumulator: f64,
+    ticks_this_frame: u32,
+    looping: bool,
+}
+
+/// The default run criteria for `TickStage`: one tick per frame while playing
+pub fn one_tick_per_frame(state: Res<State<GameState>>) -> ShouldRun {
+    if state.current() == &GameState::Playing {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Run criteria for `TickStage`, which holds both the game logic and the physics step: runs it
+/// once for every tick of time that passed since the last frame, so the game plays at the same
+/// speed at any frame rate.
+///
+/// Rapier only picks up new and removed bodies and joints between frames, so the frame ends early
+/// after a tick that added or removed any, and the next frame catches up.
+pub fn fixed_ticks(
+    time: Res<Time>,
+    state: Res<State<GameState>>,
+    mut pacing: Local<TickPacing>,
+    added_bodies: Query<(), Added<BodyId>>,
+    added_joints: Query<(), Added<PieceJoint>>,
+    removed_bodies: RemovedComponents<BodyId>,
+    removed_joints: RemovedComponents<PieceJoint>,
+) -> ShouldRun {
+    let first_run = !pacing.looping;
+
+    if first_run {
+        pacing.accumulator += time.delta_seconds_f64();
+        pacing.ticks_this_frame = 0;
+    }
+
+    if state.current() != &GameState::Playing {
+        *pacing = TickPacing::default();
+
+        return ShouldRun::No;
+    }
+
+    let physics_changed = added_bodies.iter().next().is_some()
+        || added_joints.iter().next().is_some()
+        || removed_bodies.iter().next().is_some()
+        || removed_joints.iter().next().is_some();
+
+    if pacing.ticks_this_frame > 0 && physics_changed {
+        pacing.looping = false;
+
+        return ShouldRun::No;
+    }
+
+    if pacing.accumulator < TIMESTEP {
+        pacing.looping = false;
+
+        return ShouldRun::No;
+    }
+
+    if pacing.ticks_this_frame == MAX_TICKS_PER_FRAME {
+        pacing.accumulator = 0.0;
+        pacing.looping = false;
+
+        return ShouldRun::No;
+    }
+
+    pacing.accumulator -= TIMESTEP;
+    pacing.ticks_this_frame += 1;
+    pacing.looping = true;
+
+    ShouldRun::YesAndCheckAgain
+}
@@ -0,0 +1,111 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::math::{Isometry, Vector};
+use serde::{Deserialize, Serialize};
+
+use crate::rules::Rules;
+use crate::{Block, BoardLabel, Game, TetrominoKind};
+
+/// How the blocks of a piece are held together
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PieceMode {
+    /// Four bodies connected by ball joints, so pieces bend
+    #[serde(rename = "jointed")]
+    Jointed,
+    /// One body with a collider of four squares, like classic Tetris
+    #[serde(rename = "rigid")]
+    Rigid,
+}
+
+impl Default for PieceMode {
+    fn default() -> Self {
+        Self::Jointed
+    }
+}
+
+variant_names!(
+    PieceMode,
+    "piece mode",
+    [
+        "jointed" => Jointed,
+        "rigid" => Rigid,
+    ]
+);
+
+/// Spawn a whole piece as one body, with a square collider part and sprite for each cell
+pub(crate) fn spawn_rigid_piece<B: BoardLabel>(
+    commands: &mut Commands,
+    game: &mut Game,
+    rules: &Rules,
+    kind: TetrominoKind,
+    cells: &[(i32, i32)],
+) -> Entity {
+    // The body sits in the middle of the cells, so it turns around its center of mass
+    let n_cells = cells.len() as f32;
+    let lane = cells.iter().map(|(lane, _)| *lane as f32).sum::<f32>() / n_cells;
+    let row = cells.iter().map(|(_, row)| *row as f32).sum::<f32>() / n_cells;
+    let x = game.left_wall_x() + lane + 0.5;
+    let y = game.floor_y() + row + 0.5;
+
+    // Cell centers in the local coordinates of the body
+    let offsets: Vec<Vec2> = cells
+        .iter()
+        .map(|(cell_lane, cell_row)| Vec2::new(*cell_lane as f32 - lane, *cell_row as f32 - row))
+        .collect();
+
+    let shape = ColliderShape::compound(
+        offsets
+            .iter()
+            .map(|offset| {
+                (
+                    Isometry::translation(offset.x, offset.y),
+                    ColliderShape::cuboid(0.5, 0.5),
+                )
+            })
+            .collect(),
+    );
+
+    let material = game.tetromino_colors[kind as usize].clone();
+
+    commands
+        .spawn()
+        .insert(Transform::default())
+        .insert(GlobalTransform::default())
+        .insert_bundle(RigidBodyBundle {
+            position: Isometry::new(Vector::new(x, y), 0.0).into(),
+            damping: RigidBodyDamping {
+                linear_damping: rules.linear_damping,
+                angular_damping: 0.0,
+            },
+            ..RigidBodyBundle::default()
+        })
+        .insert_bundle(ColliderBundle {
+            shape,
+            // For the lock delay
+            flags: ColliderFlags {
+                active_events: ActiveEvents::CONTACT_EVENTS,
+                ..ColliderFlags::default()
+            },
+            ..ColliderBundle::default()
+        })
+        .insert(RigidBodyPositionSync::Discrete)
+        .with_children(|parent| {
+            for offset in offsets.iter() {
+                parent.spawn_bundle(SpriteBundle {
+                    material: material.clone(),
+                    sprite: Sprite::new(Vec2::new(rules.block_px_size, rules.block_px_size)),
+                    transform: Transform::from_translation(
+                        (*offset * rules.block_px_size).extend(0.0),
+                    ),
+                    ..Default::default()
+                });
+            }
+        })
+        .insert(Block {
+            kind,
+            parts: offsets.into_iter().map(Block::square_outline).collect(),
+        })
+        .insert(game.next_body_id())
+        .insert(B::default())
+        .id()
+}
@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::TetrominoKind;
+
+/// Decides the sequence of pieces. The same seed and strategy always produce the same sequence.
+///
+/// The pieces are generated `preview_len` pieces ahead of time, so they can be shown to the player.
+pub struct PieceRandomizer {
+    seed: u64,
+    strategy_kind: RandomizerKind,
+    strategy: Box<dyn Randomizer>,
+    rng: StdRng,
+    upcoming: VecDeque<TetrominoKind>,
+}
+
+impl PieceRandomizer {
+    pub fn new(seed: u64, strategy_kind: RandomizerKind, preview_len: usize) -> Self {
+        let mut randomizer = Self {
+            seed,
+            strategy_kind,
+            strategy: strategy_kind.build(),
+            rng: StdRng::seed_from_u64(seed),
+            upcoming: VecDeque::with_capacity(preview_len + 1),
+        };
+
+        for _ in 0..preview_len {
+            let kind = randomizer.strategy.next_kind(&mut randomizer.rng);
+            randomizer.upcoming.push_back(kind);
+        }
+
+        randomizer
+    }
+
+    /// Start over with a new seed, keeping the strategy and preview length
+    pub fn reset(&mut self, seed: u64) {
+        *self = Self::new(seed, self.strategy_kind, self.upcoming.len());
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn strategy_kind(&self) -> RandomizerKind {
+        self.strategy_kind
+    }
+
+    /// The pieces that will be returned from the following calls to `next_kind`
+    pub fn upcoming(&self) -> impl Iterator<Item = TetrominoKind> + '_ {
+        self.upcoming.iter().copied()
+    }
+
+    pub fn next_kind(&mut self) -> TetrominoKind {
+        let kind = self.strategy.next_kind(&mut self.rng);
+        self.upcoming.push_back(kind);
+        self.upcoming.pop_front().unwrap()
+    }
+}
+
+/// A strategy for picking pieces. All randomness must come from the given `rng`.
+pub trait Randomizer: Send + Sync {
+    fn next_kind(&mut self, rng: &mut StdRng) -> TetrominoKind;
+}
+
+/// Selects one of the built-in randomizer strategies
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RandomizerKind {
+    #[serde(rename = "uniform")]
+    Uniform,
+    #[serde(rename = "7-bag")]
+    Bag7,
+    #[serde(rename = "14-bag")]
+    Bag14,
+    #[serde(rename = "history")]
+    History,
+}
+
+impl RandomizerKind {
+    pub fn build(&self) -> Box<dyn Randomizer> {
+        match self {
+            Self::Uniform => Box::new(Uniform),
+            Self::Bag7 => Box::new(Bag::new(1)),
+            Self::Bag14 => Box::new(Bag::new(2)),
+            Self::History => Box::new(History::new()),
+        }
+    }
+}
+
+impl Default for RandomizerKind {
+    fn default() -> Self {
+        Self::Uniform
+    }
+}
+
+variant_names!(
+    RandomizerKind,
+    "randomizer",
+    [
+        "uniform" => Uniform,
+        "7-bag" => Bag7,
+        "14-bag" => Bag14,
+        "history" => History,
+    ]
+);
+
+/// Every kind is equally likely on every draw, so droughts can be arbitrarily long
+pub struct Uniform;
+
+impl Randomizer for Uniform {
+    fn next_kind(&mut self, rng: &mut StdRng) -> TetrominoKind {
+        TetrominoKind::ALL[rng.gen_range(0..TetrominoKind::ALL.len())]
+    }
+}
+
+/// Deals pieces from a shuffled bag holding `copies` of each kind, refilling it when empty
+pub struct Bag {
+    copies: usize,
+    bag: Vec<TetrominoKind>,
+}
+
+impl Bag {
+    pub fn new(copies: usize) -> Self {
+        Self {
+            copies,
+            bag: vec![],
+        }
+    }
+}
+
+impl Randomizer for Bag {
+    fn next_kind(&mut self, rng: &mut StdRng) -> TetrominoKind {
+        if self.bag.is_empty() {
+            for _ in 0..self.copies {
+                self.bag.extend_from_slice(&TetrominoKind::ALL);
+            }
+            self.bag.shuffle(rng);
+        }
+
+        self.bag.pop().unwrap()
+    }
+}
+
+/// TGM style: re-roll a few times when the kind was one of the last four dealt
+pub struct History {
+    history: VecDeque<TetrominoKind>,
+    first: bool,
+}
+
+impl History {
+    const ROLLS: usize = 6;
+
+    pub fn new() -> Self {
+        Self {
+            history: vec![
+                TetrominoKind::Z,
+                TetrominoKind::S,
+                TetrominoKind::S,
+                TetrominoKind::Z,
+            ]
+            .into_iter()
+            .collect(),
+            first: true,
+        }
+    }
+
+    fn roll(&self, rng: &mut StdRng) -> TetrominoKind {
+        // The first piece is never one that can't be placed flat
+        let candidates: &[TetrominoKind] = if self.first {
+            &[
+                TetrominoKind::I,
+                TetrominoKind::T,
+                TetrominoKind::J,
+                TetrominoKind::L,
+            ]
+        } else {
+            &TetrominoKind::ALL
+        };
+
+        *candidates.choose(rng).unwrap()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Randomizer for History {
+    fn next_kind(&mut self, rng: &mut StdRng) -> TetrominoKind {
+        let mut kind = self.roll(rng);
+        for _ in 1..Self::ROLLS {
+            if !self.history.contains(&kind) {
+                break;
+            }
+            kind = self.roll(rng);
+        }
+
+        self.first = false;
+        self.history.pop_front();
+        self.history.push_back(kind);
+
+        kind
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deal(randomizer: &mut PieceRandomizer, count: usize) -> Vec<TetrominoKind> {
+        (0..count).map(|_| randomizer.next_kind()).collect()
+    }
+
+    #[test]
+    fn bag_deals_each_kind_once_per_bag() {
+        let mut randomizer = PieceRandomizer::new(1234, RandomizerKind::Bag7, 3);
+
+        for _ in 0..10 {
+            let bag = deal(&mut randomizer, TetrominoKind::ALL.len());
+
+            for kind in TetrominoKind::ALL.iter() {
+                let count = bag.iter().filter(|dealt| *dealt == kind).count();
+                assert_eq!(count, 1, "{:?} in {:?}", kind, bag);
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        for kind in RandomizerKind::NAMES.iter().map(|(_, kind)| *kind) {
+            let mut first = PieceRandomizer::new(42, kind, 3);
+            let mut second = PieceRandomizer::new(42, kind, 3);
+
+            assert_eq!(deal(&mut first, 100), deal(&mut second, 100), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn reset_starts_the_sequence_over() {
+        let mut randomizer = PieceRandomizer::new(7, RandomizerKind::History, 3);
+        let sequence = deal(&mut randomizer, 50);
+
+        randomizer.reset(7);
+
+        assert_eq!(deal(&mut randomizer, 50), sequence);
+    }
+}
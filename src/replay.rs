@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::controls::{Control, Controls};
+use crate::randomizer::RandomizerKind;
+use crate::rules::Rules;
+use crate::{BoardLabel, OnBoard, Tick};
+
+/// Bumped whenever the replay file format changes in an incompatible way
+pub const REPLAY_VERSION: u32 = 2;
+
+/// A recorded game: everything needed to play it again exactly the same way
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub randomizer: RandomizerKind,
+    pub rules: Rules,
+    pub events: Vec<InputEvent>,
+}
+
+/// A control being pressed or released at the start of a tick
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub tick: u64,
+    pub control: Control,
+    pub pressed: bool,
+}
+
+impl Replay {
+    pub fn new(seed: u64, randomizer: RandomizerKind, rules: Rules) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            seed,
+            randomizer,
+            rules,
+            events: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("could not read replay {}: {}", path.display(), err))?;
+
+        let replay: Self = ron::from_str(&text)
+            .map_err(|err| format!("invalid replay {}: {}", path.display(), err))?;
+
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "replay {} has version {}, only version {} is supported",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            ));
+        }
+
+        replay
+            .rules
+            .validate()
+            .map_err(|err| format!("invalid rules in replay {}: {}", path.display(), err))?;
+
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::new())
+            .map_err(|err| format!("could not serialize replay: {}", err))?;
+
+        fs::write(path, text)
+            .map_err(|err| format!("could not write replay {}: {}", path.display(), err))
+    }
+}
+
+/// Records the controls of the current game into a replay file.
+///
+/// The controls are kept in memory and written when the game ends, when the app exits
+/// or when a new game starts.
+pub struct ReplayRecorder {
+    pub path: PathBuf,
+    pub replay: Replay,
+}
+
+impl ReplayRecorder {
+    pub fn save(&self) {
+        if let Err(err) = self.replay.save(&self.path) {
+            error!("{}", err);
+        }
+    }
+}
+
+/// Feeds the controls from a replay back into the game
+pub struct ReplayPlayer {
+    events: Vec<InputEvent>,
+    next_event: usize,
+}
+
+impl ReplayPlayer {
+    /// Play the replay again from the start
+    pub fn rewind(&mut self) {
+        self.next_event = 0;
+    }
+}
+
+impl From<Replay> for ReplayPlayer {
+    fn from(replay: Replay) -> Self {
+        Self {
+            events: replay.events,
+            next_event: 0,
+        }
+    }
+}
+
+pub(crate) fn replay_controls<B: BoardLabel>(
+    tick: Res<Tick>,
+    mut player: ResMut<OnBoard<B, ReplayPlayer>>,
+    mut controls: ResMut<OnBoard<B, Controls>>,
+) {
+    controls.begin_tick();
+
+    while let Some(event) = player.events.get(player.next_event).copied() {
+        if event.tick > tick.0 {
+            break;
+        }
+
+        if event.pressed {
+            controls.press(event.control);
+        } else {
+            controls.release(event.control);
+        }
+
+        player.next_event += 1;
+    }
+}
+
+pub(crate) fn record_controls<B: BoardLabel>(
+    tick: Res<Tick>,
+    controls: Res<OnBoard<B, Controls>>,
+    mut recorder: ResMut<OnBoard<B, ReplayRecorder>>,
+) {
+    let events: Vec<InputEvent> = Control::ALL
+        .iter()
+        .filter_map(|control| {
+            if controls.just_pressed(*control) {
+                Some(true)
+            } else if controls.just_released(*control) {
+                Some(false)
+            } else {
+                None
+            }
+            .map(|pressed| InputEvent {
+                tick: tick.0,
+                control: *control,
+                pressed,
+            })
+        })
+        .collect();
+
+    recorder.replay.events.extend(events);
+}
+
+pub(crate) fn save_replay<B: BoardLabel>(recorder: Res<OnBoard<B, ReplayRecorder>>) {
+    recorder.save();
+}
+
+/// Closing the window in the middle of a game still leaves its replay behind
+pub(crate) fn save_replay_on_exit<B: BoardLabel>(
+    mut exit_events: EventReader<AppExit>,
+    recorder: Res<OnBoard<B, ReplayRecorder>>,
+) {
+    if exit_events.iter().next().is_some() {
+        recorder.save();
+    }
+}
@@ -0,0 +1,285 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::BoardShape;
+use crate::pieces::PieceMode;
+use crate::{Block, BoardLabel, OnBoard};
+
+/// Board dimensions and physics constants. Loaded from a config file, see `rules.example.ron`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Rules {
+    pub lanes: usize,
+    pub rows: usize,
+    // open, walled, gapped or funnel
+    pub board: BoardShape,
+    // jointed or rigid
+    pub pieces: PieceMode,
+    pub movement_force: f32,
+    pub torque: f32,
+    pub block_px_size: f32,
+    // In terms of block size
+    pub floor_block_height: f32,
+    // Game gets more difficult when this is lower
+    pub linear_damping: f32,
+    // In terms of block size. Blocks are lost when their center is this far below the bottom of
+    // the floor slab, or of the slopes of a funnel,
+    pub kill_depth: f32,
+    // or this far beyond the edges of the well.
+    pub kill_side_distance: f32,
+    // Seconds to wait for blocks in the way of a new piece to move. The game is over when they
+    // don't, or right away when this is zero.
+    pub spawn_wait: f32,
+    // Seconds from when a piece first touches the stack or floor until it counts as settled,
+    // even if it's still moving
+    pub lock_delay: f32,
+    // How much of a row must be covered by blocks at rest for it to clear, from 0 to 1.
+    // Tilted blocks leave gaps, so a bit less than 1 works best.
+    pub row_clear_coverage: f32,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            lanes: 10,
+            rows: 20,
+            board: BoardShape::Open,
+            pieces: PieceMode::Jointed,
+            movement_force: 20.0,
+            torque: 20.0,
+            block_px_size: 30.0,
+            floor_block_height: 2.0,
+            linear_damping: 3.0,
+            kill_depth: 2.0,
+            kill_side_distance: 2.0,
+            spawn_wait: 0.0,
+            lock_delay: 3.0,
+            row_clear_coverage: 0.9,
+        }
+    }
+}
+
+impl Rules {
+    const MIN_LANES: usize = 4;
+    const MIN_ROWS: usize = 4;
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("could not read config {}: {}", path.display(), err))?;
+
+        ron::from_str(&text).map_err(|err| format!("invalid config {}: {}", path.display(), err))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.lanes < Self::MIN_LANES {
+            return Err(format!(
+                "`lanes` must be at least {}, got {}",
+                Self::MIN_LANES,
+                self.lanes
+            ));
+        }
+
+        if self.rows < Self::MIN_ROWS {
+            return Err(format!(
+                "`rows` must be at least {}, got {}",
+                Self::MIN_ROWS,
+                self.rows
+            ));
+        }
+
+        let non_negative = [
+            ("movement_force", self.movement_force),
+            ("torque", self.torque),
+            ("linear_damping", self.linear_damping),
+            ("kill_depth", self.kill_depth),
+            ("kill_side_distance", self.kill_side_distance),
+            ("spawn_wait", self.spawn_wait),
+        ];
+        for (name, value) in non_negative.iter() {
+            if !value.is_finite() || *value < 0.0 {
+                return Err(format!("`{}` must be zero or more, got {}", name, value));
+            }
+        }
+
+        let positive = [
+            ("block_px_size", self.block_px_size),
+            ("floor_block_height", self.floor_block_height),
+            ("lock_delay", self.lock_delay),
+        ];
+        for (name, value) in positive.iter() {
+            if !value.is_finite() || *value <= 0.0 {
+                return Err(format!("`{}` must be more than zero, got {}", name, value));
+            }
+        }
+
+        if !(self.row_clear_coverage > 0.0 && self.row_clear_coverage <= 1.0) {
+            return Err(format!(
+                "`row_clear_coverage` must be more than 0 and at most 1, got {}",
+                self.row_clear_coverage
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Take the rules from `rules` that can change while the game runs.
+    /// Returns the names of the changed rules that can't.
+    pub fn update_live(&mut self, rules: &Rules) -> Vec<&'static str> {
+        let mut needs_restart = vec![];
+
+        if rules.lanes != self.lanes {
+            needs_restart.push("lanes");
+        }
+        if rules.rows != self.rows {
+            needs_restart.push("rows");
+        }
+        if rules.board != self.board {
+            needs_restart.push("board");
+        }
+        if rules.pieces != self.pieces {
+            needs_restart.push("pieces");
+        }
+        if rules.block_px_size != self.block_px_size {
+            needs_restart.push("block_px_size");
+        }
+        if rules.floor_block_height != self.floor_block_height {
+            needs_restart.push("floor_block_height");
+        }
+
+        self.movement_force = rules.movement_force;
+        self.torque = rules.torque;
+        self.linear_damping = rules.linear_damping;
+        self.kill_depth = rules.kill_depth;
+        self.kill_side_distance = rules.kill_side_distance;
+        self.spawn_wait = rules.spawn_wait;
+        self.lock_delay = rules.lock_delay;
+        self.row_clear_coverage = rules.row_clear_coverage;
+
+        needs_restart
+    }
+}
+
+/// Rules given on the command line, taking precedence over the config file
+#[derive(Clone, Default)]
+pub struct RuleOverrides {
+    pub lanes: Option<usize>,
+    pub rows: Option<usize>,
+    pub board: Option<BoardShape>,
+    pub pieces: Option<PieceMode>,
+    pub movement_force: Option<f32>,
+    pub torque: Option<f32>,
+    pub block_px_size: Option<f32>,
+    pub floor_block_height: Option<f32>,
+    pub linear_damping: Option<f32>,
+    pub kill_depth: Option<f32>,
+    pub kill_side_distance: Option<f32>,
+    pub spawn_wait: Option<f32>,
+    pub lock_delay: Option<f32>,
+    pub row_clear_coverage: Option<f32>,
+}
+
+impl RuleOverrides {
+    pub fn apply(&self, rules: &mut Rules) {
+        fn set<T: Copy>(rule: &mut T, value: Option<T>) {
+            if let Some(value) = value {
+                *rule = value;
+            }
+        }
+
+        set(&mut rules.lanes, self.lanes);
+        set(&mut rules.rows, self.rows);
+        set(&mut rules.board, self.board);
+        set(&mut rules.pieces, self.pieces);
+        set(&mut rules.movement_force, self.movement_force);
+        set(&mut rules.torque, self.torque);
+        set(&mut rules.block_px_size, self.block_px_size);
+        set(&mut rules.floor_block_height, self.floor_block_height);
+        set(&mut rules.linear_damping, self.linear_damping);
+        set(&mut rules.kill_depth, self.kill_depth);
+        set(&mut rules.kill_side_distance, self.kill_side_distance);
+        set(&mut rules.spawn_wait, self.spawn_wait);
+        set(&mut rules.lock_delay, self.lock_delay);
+        set(&mut rules.row_clear_coverage, self.row_clear_coverage);
+    }
+}
+
+/// Watches the config file, so the rules can be tuned while the game runs
+pub struct RulesWatcher {
+    path: PathBuf,
+    overrides: RuleOverrides,
+    modified: Option<SystemTime>,
+    timer: Timer,
+}
+
+impl RulesWatcher {
+    // Seconds between each look at the config file
+    const POLL_INTERVAL: f32 = 0.5;
+
+    pub fn new(path: PathBuf, overrides: RuleOverrides) -> Self {
+        let modified = modified_time(&path);
+
+        Self {
+            path,
+            overrides,
+            modified,
+            timer: Timer::from_seconds(Self::POLL_INTERVAL, true),
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+pub(crate) fn reload_rules<B: BoardLabel>(
+    time: Res<Time>,
+    mut watcher: ResMut<OnBoard<B, RulesWatcher>>,
+    mut rules: ResMut<OnBoard<B, Rules>>,
+    mut damping_query: Query<&mut RigidBodyDamping, (With<Block>, With<B>)>,
+) {
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    // The file may be missing for a moment while an editor saves it
+    let modified = modified_time(&watcher.path);
+    if modified.is_none() || modified == watcher.modified {
+        return;
+    }
+    watcher.modified = modified;
+
+    let mut new_rules = match Rules::load(&watcher.path) {
+        Ok(new_rules) => new_rules,
+        Err(err) => {
+            error!("{}, keeping the current rules", err);
+            return;
+        }
+    };
+
+    watcher.overrides.apply(&mut new_rules);
+    if let Err(err) = new_rules.validate() {
+        error!("invalid rules: {}, keeping the current rules", err);
+        return;
+    }
+
+    for name in rules.update_live(&new_rules) {
+        warn!(
+            "`{}` can't change while the game runs, restart to apply it",
+            name
+        );
+    }
+
+    // Newly spawned blocks get the damping from the rules, existing ones are updated here
+    for mut damping in damping_query.iter_mut() {
+        damping.linear_damping = rules.linear_damping;
+    }
+
+    info!("Reloaded rules from {}", watcher.path.display());
+}
@@ -0,0 +1,177 @@
+use std::f32::consts::FRAC_PI_4;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::Stats;
+
+// Points for clearing this many rows with one piece, the more at once the better
+const ROW_POINTS: [u64; 5] = [0, 100, 300, 500, 800];
+// Points for each row beyond the table above
+const EXTRA_ROW_POINTS: u64 = 400;
+
+// Each consecutive piece clearing rows adds this to the multiplier
+const COMBO_STEP: f32 = 0.5;
+
+// Each step of a chain, rows cleared by blocks falling into place after an earlier clear,
+// adds this to the multiplier
+const CHAIN_STEP: f32 = 1.0;
+
+// Perfectly aligned rows give this much extra
+const ALIGNMENT_BONUS: f32 = 0.5;
+
+/// Sent whenever rows are cleared
+#[derive(Clone, Debug)]
+pub struct ScoreEvent {
+    pub points: u64,
+    pub rows: usize,
+    /// Number of consecutive pieces that have cleared rows, including this one
+    pub combo: u32,
+    /// 1 for the rows cleared when the piece settled, 2 and up for the clears cascading from it
+    pub chain: u32,
+    /// From 0 to 1, how well the cleared blocks sat in their lanes and rows
+    pub alignment: f32,
+}
+
+/// The rows cleared after a piece settled, or when the stack came to rest again
+pub struct ClearedRows {
+    /// Counted from the floor, lowest first
+    pub rows: Vec<usize>,
+    /// Number of clears since the last piece settled, including this one
+    pub chain: u32,
+    /// The blocks removed or sliced by the cleared rows, despawned at the end of the tick
+    pub blocks: Vec<Entity>,
+    /// Outlines of the fragments left of the sliced blocks, in world coordinates. Spawned in this
+    /// tick, so not yet in the physics world.
+    pub fragments: Vec<Vec<Vec2>>,
+    /// Mean alignment of the cleared blocks
+    pub alignment: f32,
+}
+
+/// From 0 to 1, how close a block is to sitting squarely in its cell.
+///
+/// `cell_offset` is the distance from the block center, or the middle of one square of a rigid
+/// piece, to the bottom left corner of the cell it is counted in, in terms of block size.
+pub fn block_alignment(position: &RigidBodyPosition, cell_offset: (f32, f32)) -> f32 {
+    let angle = position.position.rotation.angle();
+
+    // A block turned 90 degrees is as aligned as one that isn't turned at all
+    let quarter_turn = 2.0 * FRAC_PI_4;
+    let angle_deviation = (angle - (angle / quarter_turn).round() * quarter_turn).abs();
+
+    let angle_alignment = 1.0 - angle_deviation / FRAC_PI_4;
+    let x_alignment = 1.0 - (cell_offset.0 - 0.5).abs() * 2.0;
+    let y_alignment = 1.0 - (cell_offset.1 - 0.5).abs() * 2.0;
+
+    (angle_alignment * x_alignment * y_alignment).max(0.0)
+}
+
+fn row_points(rows: usize) -> u64 {
+    match ROW_POINTS.get(rows) {
+        Some(points) => *points,
+        None => ROW_POINTS[ROW_POINTS.len() - 1] + (rows - 4) as u64 * EXTRA_ROW_POINTS,
+    }
+}
+
+/// Update score and combo after a piece has settled, or rows were cleared in a chain
+pub fn award(stats: &mut Stats, cleared: &ClearedRows) -> Option<ScoreEvent> {
+    if cleared.rows.is_empty() {
+        stats.combo = 0;
+        return None;
+    }
+
+    // The combo counts pieces, the chain counts clears after the same piece
+    if cleared.chain == 1 {
+        stats.combo += 1;
+    }
+    stats.longest_chain = stats.longest_chain.max(cleared.chain);
+
+    let combo_multiplier = 1.0 + COMBO_STEP * stats.combo.saturating_sub(1) as f32;
+    let chain_multiplier = 1.0 + CHAIN_STEP * (cleared.chain - 1) as f32;
+    let alignment_multiplier = 1.0 + ALIGNMENT_BONUS * cleared.alignment;
+    let points = (row_points(cleared.rows.len()) as f32
+        * combo_multiplier
+        * chain_multiplier
+        * alignment_multiplier)
+        .round() as u64;
+
+    stats.score += points;
+
+    Some(ScoreEvent {
+        points,
+        rows: cleared.rows.len(),
+        combo: stats.combo,
+        chain: cleared.chain,
+        alignment: cleared.alignment,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    use super::*;
+
+    fn cleared(rows: usize, chain: u32) -> ClearedRows {
+        ClearedRows {
+            rows: (0..rows).collect(),
+            chain,
+            blocks: vec![],
+            fragments: vec![],
+            alignment: 0.0,
+        }
+    }
+
+    fn body_at(angle: f32) -> RigidBodyPosition {
+        Isometry::new(Vector::new(0.0, 0.0), angle).into()
+    }
+
+    #[test]
+    fn piece_clearing_nothing_ends_the_combo() {
+        let mut stats = Stats::default();
+        award(&mut stats, &cleared(1, 1));
+        award(&mut stats, &cleared(1, 1));
+        assert_eq!(stats.combo, 2);
+
+        assert!(award(&mut stats, &cleared(0, 1)).is_none());
+        assert_eq!(stats.combo, 0);
+
+        let event = award(&mut stats, &cleared(1, 1)).unwrap();
+        assert_eq!(event.combo, 1);
+        assert_eq!(event.points, row_points(1));
+    }
+
+    #[test]
+    fn chain_step_is_not_a_combo_step() {
+        let mut stats = Stats::default();
+        award(&mut stats, &cleared(1, 1));
+
+        let event = award(&mut stats, &cleared(1, 2)).unwrap();
+        assert_eq!(event.combo, 1);
+        assert_eq!(event.chain, 2);
+        assert_eq!(stats.combo, 1);
+        assert_eq!(stats.longest_chain, 2);
+        assert_eq!(
+            event.points,
+            (row_points(1) as f32 * (1.0 + CHAIN_STEP)) as u64
+        );
+    }
+
+    #[test]
+    fn rows_beyond_the_table_add_extra_points() {
+        assert_eq!(row_points(4), ROW_POINTS[4]);
+        assert_eq!(row_points(5), ROW_POINTS[4] + EXTRA_ROW_POINTS);
+        assert_eq!(row_points(7), ROW_POINTS[4] + 3 * EXTRA_ROW_POINTS);
+    }
+
+    #[test]
+    fn quarter_turns_are_fully_aligned() {
+        for angle in [FRAC_PI_2, -FRAC_PI_2, PI].iter() {
+            let alignment = block_alignment(&body_at(*angle), (0.5, 0.5));
+            assert!((alignment - 1.0).abs() < 1e-5, "{} at {}", alignment, angle);
+        }
+
+        let alignment = block_alignment(&body_at(FRAC_PI_4), (0.5, 0.5));
+        assert!(alignment.abs() < 1e-5, "{} at 45 degrees", alignment);
+    }
+}
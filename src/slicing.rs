@@ -0,0 +1,165 @@
+//! Cutting blocks along the edges of cleared rows
+
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy::render::pipeline::PrimitiveTopology;
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::math::{Isometry, Point};
+
+use crate::coverage::{area, clip, world_polygon};
+use crate::Game;
+
+// In terms of block size squared. Slivers smaller than this are removed with the row, and blocks
+// with less than this inside cleared rows are not sliced at all.
+pub(crate) const MIN_FRAGMENT_AREA: f32 = 0.02;
+
+/// What is left of a block outside the cleared rows, one body per gap between them, each made of
+/// the surviving parts in the local coordinates of the block.
+/// `cleared_rows` must be sorted, lowest first.
+pub fn fragments_outside_rows(
+    game: &Game,
+    position: &RigidBodyPosition,
+    parts: &[Vec<Vec2>],
+    cleared_rows: &[usize],
+) -> Vec<Vec<Vec<Vec2>>> {
+    let world_parts: Vec<Vec<Vec2>> = parts
+        .iter()
+        .map(|part| world_polygon(position, part))
+        .collect();
+
+    // The gaps between the cleared rows, as (bottom, top)
+    let mut gaps = vec![];
+    let mut gap_bottom = f32::NEG_INFINITY;
+    for row in cleared_rows {
+        let row_bottom = game.floor_y() + *row as f32;
+        gaps.push((gap_bottom, row_bottom));
+        gap_bottom = row_bottom + 1.0;
+    }
+    gaps.push((gap_bottom, f32::INFINITY));
+
+    gaps.into_iter()
+        .map(|(bottom, top)| {
+            world_parts
+                .iter()
+                .map(|world| {
+                    let fragment = clip(world, |point| point.y - bottom);
+                    clip(&fragment, |point| top - point.y)
+                })
+                .filter(|fragment| area(fragment) >= MIN_FRAGMENT_AREA)
+                .map(|fragment| {
+                    fragment
+                        .into_iter()
+                        .map(|point| {
+                            let point = position
+                                .position
+                                .inverse_transform_point(&Point::new(point.x, point.y));
+                            Vec2::new(point.x, point.y)
+                        })
+                        .collect()
+                })
+                .collect::<Vec<Vec<Vec2>>>()
+        })
+        .filter(|fragment_parts| !fragment_parts.is_empty())
+        .collect()
+}
+
+/// A collider for convex parts, a compound one if there are several. None if they are all too
+/// thin to have one.
+pub fn parts_collider(parts: &[Vec<Vec2>]) -> Option<ColliderShape> {
+    let mut shapes: Vec<ColliderShape> = parts
+        .iter()
+        .filter_map(|part| polygon_collider(part))
+        .collect();
+
+    match shapes.len() {
+        0 => None,
+        1 => shapes.pop(),
+        _ => Some(ColliderShape::compound(
+            shapes
+                .into_iter()
+                .map(|shape| (Isometry::identity(), shape))
+                .collect(),
+        )),
+    }
+}
+
+/// A collider for a convex polygon, None if it's too thin to have one
+fn polygon_collider(polygon: &[Vec2]) -> Option<ColliderShape> {
+    let points: Vec<Point<f32>> = polygon
+        .iter()
+        .map(|point| Point::new(point.x, point.y))
+        .collect();
+
+    ColliderShape::convex_hull(&points)
+}
+
+/// A mesh for convex parts, to be drawn as a sprite with the size of one block
+pub fn parts_mesh(parts: &[Vec<Vec2>]) -> Mesh {
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+
+    for part in parts {
+        // A fan of triangles from the first corner of each part
+        let first = positions.len() as u32;
+        indices
+            .extend((1..part.len() as u32 - 1).flat_map(|i| vec![first, first + i, first + i + 1]));
+        positions.extend(part.iter().map(|point| [point.x, point.y, 0.0]));
+    }
+
+    let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+    let uvs = vec![[0.0, 0.0]; positions.len()];
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_4;
+
+    use bevy_rapier2d::rapier::math::Vector;
+
+    use super::*;
+    use crate::rules::Rules;
+    use crate::test_util::assert_close;
+    use crate::Block;
+
+    fn fragment_area(parts: &[Vec<Vec2>]) -> f32 {
+        parts.iter().map(|part| area(part)).sum()
+    }
+
+    #[test]
+    fn square_across_a_cleared_row_loses_its_part_inside() {
+        let game = Game::new(&Rules::default(), Vec2::ZERO);
+        // A quarter of it in the first row, the rest in the second
+        let position = Isometry::new(Vector::new(0.5, game.floor_y() + 1.25), 0.0).into();
+        let parts = vec![Block::square_outline(Vec2::ZERO)];
+
+        let fragments = fragments_outside_rows(&game, &position, &parts, &[1]);
+
+        assert_eq!(fragments.len(), 1);
+        assert_close(fragment_area(&fragments[0]), 0.25);
+    }
+
+    #[test]
+    fn tilted_square_through_a_cleared_row_leaves_two_fragments() {
+        let game = Game::new(&Rules::default(), Vec2::ZERO);
+        // Centered in the second row, corners up and down sticking out of it
+        let position = Isometry::new(Vector::new(0.5, game.floor_y() + 1.5), FRAC_PI_4).into();
+        let parts = vec![Block::square_outline(Vec2::ZERO)];
+
+        let fragments = fragments_outside_rows(&game, &position, &parts, &[1]);
+
+        // Each corner is a right triangle as high as it sticks out and twice as wide
+        let height = FRAC_PI_4.cos() - 0.5;
+        assert_eq!(fragments.len(), 2);
+        for fragment in fragments.iter() {
+            assert_close(fragment_area(fragment), height * height);
+        }
+    }
+}
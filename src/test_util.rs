@@ -0,0 +1,11 @@
+//! Helpers shared by the unit tests
+
+/// Areas and lengths come out of floating point geometry, so only compare them roughly
+pub(crate) fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() < 1e-4,
+        "expected {}, got {}",
+        expected,
+        actual
+    );
+}
@@ -0,0 +1,124 @@
+//! Two boards in one headless app, side by side in one physics world
+
+use bevy::asset::AssetPlugin;
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use bevy::transform::TransformPlugin;
+use bevy_rapier2d::prelude::*;
+use newtonian_tetris::board::BoardShape;
+use newtonian_tetris::controls::{Control, Controls, InputSource};
+use newtonian_tetris::rules::Rules;
+use newtonian_tetris::{BoardLabel, Game, GameConfig, NewtonianTetrisPlugin, OnBoard};
+
+#[derive(Default)]
+struct LeftBoard;
+
+#[derive(Default)]
+struct RightBoard;
+
+// 20 seconds, time enough for the first pieces to fall all the way and settle
+const TICKS: usize = 1200;
+
+// Half a second, while the first pieces are still falling
+const STEERING_TICKS: usize = 30;
+
+fn config(origin_x: f32) -> GameConfig {
+    GameConfig {
+        rules: Rules {
+            board: BoardShape::Walled,
+            ..Rules::default()
+        },
+        seed: Some(1),
+        headless: true,
+        origin: Vec2::new(origin_x, 0.0),
+        ..GameConfig::default()
+    }
+}
+
+/// Horizontal positions of the bodies of the board labelled `B`
+fn body_xs<B: BoardLabel>(world: &mut World) -> Vec<f32> {
+    world
+        .query_filtered::<&RigidBodyPosition, With<B>>()
+        .iter(world)
+        .map(|position| position.position.translation.x)
+        .collect()
+}
+
+fn two_board_app(left: GameConfig, right: GameConfig) -> AppBuilder {
+    let mut app = App::build();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin::default())
+        .add_plugin(InputPlugin::default())
+        .add_plugin(AssetPlugin::default())
+        .add_asset::<ColorMaterial>()
+        .add_asset::<Mesh>()
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_plugin(NewtonianTetrisPlugin::<LeftBoard>::for_board(left))
+        .add_plugin(NewtonianTetrisPlugin::<RightBoard>::for_board(right));
+    app
+}
+
+#[test]
+fn boards_play_side_by_side() {
+    let mut app = two_board_app(config(-20.0), config(20.0));
+
+    for _ in 0..TICKS {
+        app.app.update();
+    }
+
+    let world = &mut app.app.world;
+
+    let left = &world
+        .get_resource::<OnBoard<LeftBoard, Game>>()
+        .unwrap()
+        .stats;
+    assert!(left.settled_pieces > 0);
+    assert!(left.generated_blocks > 4);
+
+    let right = &world
+        .get_resource::<OnBoard<RightBoard, Game>>()
+        .unwrap()
+        .stats;
+    assert!(right.settled_pieces > 0);
+    assert!(right.generated_blocks > 4);
+
+    // Each board keeps to its own side of the shared physics world
+    let left_xs = body_xs::<LeftBoard>(world);
+    assert!(!left_xs.is_empty());
+    assert!(left_xs.iter().all(|x| *x < 0.0));
+
+    let right_xs = body_xs::<RightBoard>(world);
+    assert!(!right_xs.is_empty());
+    assert!(right_xs.iter().all(|x| *x > 0.0));
+}
+
+#[test]
+fn controls_steer_their_own_board() {
+    let external = |origin_x| GameConfig {
+        input: InputSource::External,
+        ..config(origin_x)
+    };
+    let mut app = two_board_app(external(-20.0), external(20.0));
+
+    // Enters the game and spawns the first pieces
+    for _ in 0..2 {
+        app.app.update();
+    }
+    let left_start: f32 = body_xs::<LeftBoard>(&mut app.app.world).iter().sum();
+    let right_start: f32 = body_xs::<RightBoard>(&mut app.app.world).iter().sum();
+
+    app.app
+        .world
+        .get_resource_mut::<OnBoard<LeftBoard, Controls>>()
+        .unwrap()
+        .press(Control::Left);
+    for _ in 0..STEERING_TICKS {
+        app.app.update();
+    }
+
+    // Both boards have the same seed, so the same pieces, but only the left one is pushed
+    let left_end: f32 = body_xs::<LeftBoard>(&mut app.app.world).iter().sum();
+    let right_end: f32 = body_xs::<RightBoard>(&mut app.app.world).iter().sum();
+    assert!(left_end < left_start - 0.1);
+    assert!((right_end - right_start).abs() < 1e-3);
+}